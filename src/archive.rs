@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::{Context, Result, bail};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+/// Pacotes lidos como diretórios, pela extensão.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Kind {
+    fn of(path: &Path) -> Option<Kind> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Kind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Kind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Kind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Diz se o arquivo é um pacote (.zip, .tar, .tar.gz ou .tgz).
+pub fn is_archive(path: &Path) -> bool {
+    Kind::of(path).is_some()
+}
+
+/// Nome do pacote sem a extensão (`bundle.tar.gz` vira `bundle`), usado como
+/// pasta das entradas na saída quando o pacote está dentro de um diretório.
+pub fn stem(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let end = [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find_map(|ext| {
+            let start = name.len().checked_sub(ext.len())?;
+            name.get(start..)
+                .filter(|tail| tail.eq_ignore_ascii_case(ext))
+                .map(|_| start)
+        })
+        .unwrap_or(name.len());
+    PathBuf::from(&name[..end])
+}
+
+/// Caminho virtual de uma entrada, como `bundle.zip!/images/a.png`.
+pub fn virtual_path(archive: &Path, name: &Path) -> PathBuf {
+    let name: Vec<_> = name
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    PathBuf::from(format!("{}!/{}", archive.display(), name.join("/")))
+}
+
+/// Diz se o caminho virtual é de uma entrada do pacote.
+pub fn contains(archive: &Path, path: &Path) -> bool {
+    path.to_string_lossy()
+        .starts_with(&format!("{}!/", archive.display()))
+}
+
+/// Arquivo dentro de um pacote, lido só quando a imagem é processada.
+#[derive(Clone)]
+pub struct Entry {
+    archive: PathBuf,
+    /// Caminho dentro do pacote
+    name: PathBuf,
+    /// Tamanho descompactado, como declarado no pacote
+    size: u64,
+    location: Location,
+}
+
+#[derive(Clone)]
+enum Location {
+    /// O zip aberto é compartilhado pelas entradas do mesmo pacote
+    Zip {
+        zip: Arc<Mutex<ZipArchive<BufReader<File>>>>,
+        index: usize,
+    },
+    /// Trecho do .tar no disco
+    Tar { offset: u64 },
+    /// N-ésima entrada do .tar.gz, descompactada na primeira leitura
+    TarGz { spool: Arc<Spool>, number: usize },
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("archive", &self.archive)
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Entry {
+    /// Pacote de onde a entrada vem.
+    pub fn archive(&self) -> &Path {
+        &self.archive
+    }
+
+    /// Caminho da entrada dentro do pacote.
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+
+    /// Tamanho da entrada descompactada.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Lê o conteúdo inteiro da entrada.
+    ///
+    /// Entradas maiores que `max` (o limite de --max-alloc) são recusadas sem
+    /// descompactar, já que um pacote pequeno pode se expandir para muito
+    /// mais do que ocupa no disco.
+    pub fn read(&self, max: u64) -> Result<Vec<u8>> {
+        let data = self.read_up_to(max.saturating_add(1), max)?;
+        if data.len() as u64 > max {
+            bail!("{}", self.too_large(data.len() as u64, max));
+        }
+        Ok(data)
+    }
+
+    /// Lê só o começo da entrada (até `len` bytes), para achar o cabeçalho.
+    pub fn read_prefix(&self, len: u64, max: u64) -> Result<Vec<u8>> {
+        self.read_up_to(len, max)
+    }
+
+    fn read_up_to(&self, limit: u64, max: u64) -> Result<Vec<u8>> {
+        if self.size > max {
+            bail!("{}", self.too_large(self.size, max));
+        }
+
+        let mut data = Vec::new();
+        match &self.location {
+            Location::Zip { zip, index } => {
+                // Um pânico com o zip em uso não impede as outras entradas
+                let mut zip = zip.lock().unwrap_or_else(PoisonError::into_inner);
+                zip.by_index(*index)?.take(limit).read_to_end(&mut data)?;
+            }
+            Location::Tar { offset } => {
+                let mut file = File::open(&self.archive)?;
+                file.seek(SeekFrom::Start(*offset))?;
+                file.take(self.size.min(limit)).read_to_end(&mut data)?;
+            }
+            Location::TarGz { spool, number } => {
+                spool.read(*number, limit, max, &mut data)?;
+            }
+        }
+        Ok(data)
+    }
+
+    fn too_large(&self, size: u64, max: u64) -> String {
+        format!(
+            "{} no pacote tem {:.1} MiB descompactado, acima do limite de {:.1} MiB (--max-alloc)",
+            self.name.display(),
+            size as f64 / MIB,
+            max as f64 / MIB
+        )
+    }
+}
+
+const MIB: f64 = (1 << 20) as f64;
+
+/// Conteúdo de um .tar.gz. Como não dá para pular direto para uma entrada
+/// dele, na primeira leitura as entradas são descompactadas para um arquivo
+/// temporário (apagado no fim), em vez de ficarem na memória.
+struct Spool {
+    archive: PathBuf,
+    extracted: Mutex<Option<Extracted>>,
+}
+
+struct Extracted {
+    file: File,
+    /// Posição e tamanho no arquivo temporário, pelo número da entrada
+    entries: HashMap<usize, (u64, u64)>,
+}
+
+impl Spool {
+    /// Lê até `limit` bytes da entrada `number`, descompactando o pacote se
+    /// ainda não foi; entradas maiores que `max` ficam de fora.
+    fn read(&self, number: usize, limit: u64, max: u64, data: &mut Vec<u8>) -> Result<()> {
+        let mut extracted = self
+            .extracted
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if extracted.is_none() {
+            *extracted = Some(self.extract(max)?);
+        }
+        let Some(Extracted { file, entries }) = extracted.as_mut() else {
+            unreachable!("o pacote acabou de ser descompactado");
+        };
+
+        let Some(&(offset, size)) = entries.get(&number) else {
+            bail!("entrada não encontrada ao descompactar o pacote");
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        file.take(size.min(limit)).read_to_end(data)?;
+        Ok(())
+    }
+
+    fn extract(&self, max: u64) -> Result<Extracted> {
+        let mut file = tempfile::tempfile()?;
+        let mut entries = HashMap::new();
+        let mut offset = 0;
+
+        let mut tar = tar::Archive::new(gz_reader(&self.archive)?);
+        for (number, entry) in tar.entries()?.enumerate() {
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() || entry.size() > max {
+                continue;
+            }
+            let size = io::copy(&mut entry.take(max), &mut file)?;
+            entries.insert(number, (offset, size));
+            offset += size;
+        }
+        Ok(Extracted { file, entries })
+    }
+}
+
+fn gz_reader(archive: &Path) -> Result<GzDecoder<BufReader<File>>> {
+    Ok(GzDecoder::new(BufReader::new(File::open(archive)?)))
+}
+
+/// Lista os arquivos de um pacote, ordenados pelo caminho dentro dele.
+///
+/// Pastas, links e entradas com caminhos que sairiam do pacote (`../`) são
+/// pulados. Nada é descompactado aqui: um .tar.gz é percorrido só para
+/// achar os nomes.
+pub fn list(archive: &Path) -> Result<Vec<(PathBuf, Entry)>> {
+    let mut entries = read_entries(archive)
+        .with_context(|| format!("não foi possível ler {}", archive.display()))?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+fn read_entries(archive: &Path) -> Result<Vec<(PathBuf, Entry)>> {
+    match Kind::of(archive) {
+        Some(Kind::Zip) => list_zip(archive),
+        Some(Kind::Tar) => list_tar(archive),
+        Some(Kind::TarGz) => list_tar_gz(archive),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn list_zip(archive: &Path) -> Result<Vec<(PathBuf, Entry)>> {
+    let mut zip = ZipArchive::new(BufReader::new(File::open(archive)?))?;
+    let mut files = Vec::new();
+    for index in 0..zip.len() {
+        let file = zip.by_index_raw(index)?;
+        let name = file.enclosed_name().filter(|_| file.is_file());
+        if let Some(name) = name.as_deref().and_then(entry_name) {
+            files.push((name, index, file.size()));
+        }
+    }
+
+    let zip = Arc::new(Mutex::new(zip));
+    Ok(files
+        .into_iter()
+        .map(|(name, index, size)| {
+            let location = Location::Zip {
+                zip: zip.clone(),
+                index,
+            };
+            let entry = Entry {
+                archive: archive.to_path_buf(),
+                name: name.clone(),
+                size,
+                location,
+            };
+            (name, entry)
+        })
+        .collect())
+}
+
+fn list_tar(archive: &Path) -> Result<Vec<(PathBuf, Entry)>> {
+    let mut tar = tar::Archive::new(File::open(archive)?);
+    let mut entries = Vec::new();
+    for entry in tar.entries_with_seek()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(name) = entry_name(&entry.path()?) else {
+            continue;
+        };
+
+        let location = Location::Tar {
+            offset: entry.raw_file_position(),
+        };
+        let entry = Entry {
+            archive: archive.to_path_buf(),
+            name: name.clone(),
+            size: entry.size(),
+            location,
+        };
+        entries.push((name, entry));
+    }
+    Ok(entries)
+}
+
+fn list_tar_gz(archive: &Path) -> Result<Vec<(PathBuf, Entry)>> {
+    let spool = Arc::new(Spool {
+        archive: archive.to_path_buf(),
+        extracted: Mutex::new(None),
+    });
+
+    let mut tar = tar::Archive::new(gz_reader(archive)?);
+    let mut entries = Vec::new();
+    for (number, entry) in tar.entries()?.enumerate() {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(name) = entry_name(&entry.path()?) else {
+            continue;
+        };
+
+        let location = Location::TarGz {
+            spool: spool.clone(),
+            number,
+        };
+        let entry = Entry {
+            archive: archive.to_path_buf(),
+            name: name.clone(),
+            size: entry.size(),
+            location,
+        };
+        entries.push((name, entry));
+    }
+    Ok(entries)
+}
+
+/// Caminho de uma entrada sem o `./` do começo (`tar czf b.tgz -C pasta .`
+/// gera `./images/a.png`). `None` para caminhos vazios ou que sairiam do
+/// pacote (`../`, absolutos).
+fn entry_name(path: &Path) -> Option<PathBuf> {
+    let mut name = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => name.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!name.as_os_str().is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nome_da_entrada_sem_ponto_barra() {
+        assert_eq!(
+            entry_name(Path::new("./a.png")),
+            Some(PathBuf::from("a.png"))
+        );
+        assert_eq!(
+            entry_name(Path::new("./images/./b.png")),
+            Some(PathBuf::from("images/b.png"))
+        );
+        assert_eq!(
+            entry_name(Path::new("images/b.png")),
+            Some(PathBuf::from("images/b.png"))
+        );
+    }
+
+    #[test]
+    fn nome_que_sai_do_pacote_e_pulado() {
+        for name in [
+            "../x.png",
+            "images/../../x.png",
+            "/etc/x.png",
+            ".",
+            "./",
+            "",
+        ] {
+            assert_eq!(entry_name(Path::new(name)), None, "{name:?}");
+        }
+    }
+}
@@ -0,0 +1,749 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::num::NonZero;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::{Result, anyhow, bail};
+use clap::{Args, ValueEnum};
+
+use crate::cache::{Cache, CacheKey};
+use crate::job::{
+    self, Action, ImageReport, InputFile, Job, Layout, Limits, MemoryBudget, Rendition,
+};
+use crate::naming::NameTemplate;
+use crate::target_size;
+
+/// Opções de execução do lote, aceitas por todos os subcomandos.
+#[derive(Args, Debug)]
+pub struct BatchOptions {
+    /// Quantidade de imagens processadas em paralelo (padrão: número de CPUs).
+    /// A saída no console e o relatório seguem sempre a ordem dos arquivos
+    #[arg(short, long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: Option<u32>,
+
+    /// Memória total para imagens decodificadas ao mesmo tempo (ex: 4GB); quando
+    /// cheia, as próximas imagens esperam em vez de decodificar em paralelo
+    #[arg(long, global = true, value_parser = target_size::parse_byte_size)]
+    memory_budget: Option<u64>,
+
+    /// Recusa imagens com largura ou altura acima deste valor, em pixels
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    max_dimension: Option<u32>,
+
+    /// Memória máxima para decodificar uma única imagem (ex: 512MiB, 2GB)
+    #[arg(long, global = true, default_value = "512MiB", value_parser = target_size::parse_byte_size)]
+    max_alloc: u64,
+
+    /// Grava todas as saídas direto no diretório de saída, sem repetir as
+    /// subpastas da entrada
+    #[arg(long, global = true)]
+    flatten: bool,
+
+    /// Modelo do nome dos arquivos de saída, relativo ao diretório de saída.
+    /// Placeholders: {stem}, {dir}, {width}, {height}, {format}, {ext},
+    /// {preset}, {variant}, {hash} (ou {hash:N}) e {counter} (ou {counter:N}).
+    /// Ex: "{stem}-{width}x{height}.{ext}" ou "{dir}/{stem}.{hash}.{ext}"
+    #[arg(long, global = true, value_name = "MODELO")]
+    name: Option<NameTemplate>,
+
+    /// O que fazer quando duas entradas geram o mesmo arquivo de saída
+    #[arg(long, global = true, value_enum, default_value_t = Collision::Suffix)]
+    on_collision: Collision,
+
+    /// O que fazer quando o arquivo de saída já existe antes da execução.
+    /// Arquivos de entrada nunca são sobrescritos
+    #[arg(long, global = true, value_enum, default_value_t = Overwrite::Always)]
+    overwrite: Overwrite,
+
+    /// Pula entradas que não mudaram desde a última execução, usando um
+    /// manifesto no diretório de saída com o hash de cada entrada e das
+    /// configurações que a geraram
+    #[arg(long, global = true)]
+    incremental: bool,
+
+    /// Mostra (e relata) o que seria gravado, com formatos e dimensões, sem
+    /// decodificar as imagens nem criar arquivos ou diretórios de saída
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+}
+
+/// Política para saídas repetidas dentro da mesma execução.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collision {
+    /// Acrescenta -1, -2, ... ao nome do arquivo repetido
+    Suffix,
+    /// Não grava o arquivo repetido e reporta erro
+    Error,
+    /// Grava por cima (a última entrada vence)
+    Overwrite,
+}
+
+/// Política para arquivos de saída que já existiam.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Mantém o arquivo existente
+    Never,
+    /// Substitui o arquivo existente
+    Always,
+    /// Substitui só se a entrada foi modificada depois da saída
+    IfNewer,
+    /// Grava ao lado, acrescentando -1, -2, ... ao nome
+    Suffix,
+}
+
+impl BatchOptions {
+    fn jobs(&self) -> usize {
+        self.jobs.map_or_else(
+            || thread::available_parallelism().map_or(1, NonZero::get),
+            |n| n as usize,
+        )
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_dimension: self.max_dimension,
+            max_alloc: self.max_alloc,
+        }
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            flatten: self.flatten,
+            name: self.name.clone(),
+        }
+    }
+}
+
+/// Resultado do processamento de um arquivo.
+enum Outcome {
+    Ignored,
+    Failed(anyhow::Error),
+    /// Resultado de cada variante, pelo índice no job
+    Done(Vec<(usize, Result<Rendition>)>),
+}
+
+/// Processa todos os arquivos com o job, devolvendo o relatório de cada saída.
+///
+/// Cada imagem é decodificada uma única vez e reaproveitada por todas as
+/// variantes do job. Até `--jobs` arquivos são processados ao mesmo tempo,
+/// dentro do orçamento de memória, mas os arquivos são gravados, impressos e
+/// relatados na ordem de `files`, então colisões são resolvidas sempre do
+/// mesmo jeito.
+pub fn process_all(
+    files: Vec<InputFile>,
+    job: &Job,
+    batch: &BatchOptions,
+) -> Result<Vec<ImageReport>> {
+    process_all_with(files, job, batch, HashSet::new())
+}
+
+/// Como [`process_all`], mas com saídas que já pertencem a outras entradas
+/// (`claimed`), que passam pela política de `--on-collision` como se tivessem
+/// sido geradas nesta execução.
+pub fn process_all_with(
+    files: Vec<InputFile>,
+    job: &Job,
+    batch: &BatchOptions,
+    claimed: HashSet<PathBuf>,
+) -> Result<Vec<ImageReport>> {
+    // Garante que os diretórios de saída existem
+    if !batch.dry_run {
+        for variant in &job.variants {
+            fs::create_dir_all(&variant.output)?;
+        }
+    }
+
+    let layout = batch.layout();
+    let mut writer = Writer {
+        layout: layout.clone(),
+        collision: batch.on_collision,
+        overwrite: batch.overwrite,
+        inputs: files
+            .iter()
+            .filter_map(|f| f.path.canonicalize().ok())
+            .collect(),
+        claimed,
+        dry_run: batch.dry_run,
+        counter: 0,
+        reports: Vec::new(),
+        records: Vec::new(),
+    };
+
+    if files.is_empty() {
+        eprintln!("Nenhum arquivo encontrado para processar.");
+        return Ok(writer.reports);
+    }
+
+    println!("Encontrados {} arquivo(s) para processar.", files.len());
+
+    let worker = Worker {
+        job,
+        limits: batch.limits(),
+        budget: MemoryBudget::new(batch.memory_budget),
+        dry_run: batch.dry_run,
+        cache: if batch.incremental {
+            Some(Cache::load(job, &layout)?)
+        } else {
+            None
+        },
+    };
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..batch.jobs().min(files.len()) {
+            let sender = sender.clone();
+            let (next, files, worker) = (&next, &files, &worker);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = files.get(index) else { break };
+                    if sender.send((index, worker.process_guarded(file))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Segura os resultados que chegam adiantados até chegar a vez deles
+        let mut pending = BTreeMap::new();
+        let mut next_to_write = 0;
+        for (index, outcome) in receiver {
+            pending.insert(index, outcome);
+            while let Some(outcome) = pending.remove(&next_to_write) {
+                writer.finish(&files[next_to_write], outcome, &worker);
+                next_to_write += 1;
+            }
+        }
+    });
+
+    if let Some(cache) = worker.cache
+        && !batch.dry_run
+    {
+        cache.save(writer.records)?;
+    }
+
+    Ok(writer.reports)
+}
+
+/// O que cada thread precisa para processar um arquivo.
+struct Worker<'a> {
+    job: &'a Job,
+    limits: Limits,
+    budget: MemoryBudget,
+    dry_run: bool,
+    cache: Option<Cache>,
+}
+
+impl Worker<'_> {
+    /// Como [`Worker::process`], mas um pânico ao processar o arquivo (ex: num
+    /// encoder) vira uma falha só dele, sem derrubar o lote inteiro.
+    fn process_guarded(&self, file: &InputFile) -> Outcome {
+        panic::catch_unwind(AssertUnwindSafe(|| self.process(file))).unwrap_or_else(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("erro desconhecido");
+            Outcome::Failed(anyhow!("falha inesperada ao processar: {message}"))
+        })
+    }
+
+    /// Decodifica um arquivo e gera todas as variantes que se aplicam a ele.
+    ///
+    /// O cabeçalho é lido primeiro para recusar imagens acima dos limites e
+    /// reservar a memória estimada antes de decodificar. Variantes que não
+    /// mudaram desde a última execução (--incremental) vêm do manifesto, e
+    /// com `dry_run` as demais são só planejadas a partir do cabeçalho.
+    fn process(&self, file: &InputFile) -> Outcome {
+        let header = match job::read_header(file, &self.limits) {
+            Ok(Some(header)) => header,
+            Ok(None) => return Outcome::Ignored,
+            Err(e) => return Outcome::Failed(e),
+        };
+        if let Err(e) = self.limits.check(&header) {
+            return Outcome::Failed(e);
+        }
+
+        let variants: Vec<usize> = (0..self.job.variants.len())
+            .filter(|&i| self.job.variants[i].applies_to(header.width, header.height))
+            .collect();
+
+        // Entradas de pacotes são lidas uma vez só, para o hash e para
+        // decodificar, já com a memória de tudo reservada: reservar aos
+        // poucos poderia travar com outra thread esperando o mesmo orçamento
+        let mut reservation = None;
+        let mut data = None;
+        if let Some(entry) = &file.entry
+            && (self.cache.is_some() || !self.dry_run)
+        {
+            let bytes = header.peak_bytes(variants.iter().map(|&i| &self.job.variants[i]));
+            reservation = Some(self.budget.reserve(bytes));
+            data = match entry.read(self.limits.max_alloc) {
+                Ok(data) => Some(data),
+                Err(e) => return Outcome::Failed(e),
+            };
+        }
+
+        let keys = match &self.cache {
+            Some(cache) => match cache.keys(file, data.as_deref(), self.job, &variants) {
+                Ok(keys) => keys.into_iter().map(Some).collect(),
+                Err(e) => return Outcome::Failed(e),
+            },
+            None => vec![None; variants.len()],
+        };
+
+        let mut results: Vec<Option<Result<Rendition>>> = Vec::new();
+        let mut missing = Vec::new();
+        for (position, (&index, key)) in variants.iter().zip(keys).enumerate() {
+            let cached = key
+                .as_ref()
+                .zip(self.cache.as_ref())
+                .and_then(|(key, cache)| cache.lookup(key));
+            match cached {
+                Some(report) => {
+                    let variant = &self.job.variants[index];
+                    let rendition = Rendition::unchanged(file, variant, report);
+                    results.push(Some(Ok(Rendition {
+                        cache: key,
+                        ..rendition
+                    })));
+                }
+                None => {
+                    results.push(None);
+                    missing.push((position, index, key));
+                }
+            }
+        }
+
+        if self.dry_run {
+            for (position, index, _) in missing {
+                let variant = &self.job.variants[index];
+                results[position] = Some(Ok(job::plan_image(&header, variant)));
+            }
+        } else if !missing.is_empty() {
+            let _reservation = reservation.unwrap_or_else(|| {
+                let variants = missing
+                    .iter()
+                    .map(|&(_, index, _)| &self.job.variants[index]);
+                self.budget.reserve(header.peak_bytes(variants))
+            });
+            let source = match job::decode(header, data.as_deref(), &self.limits) {
+                Ok(source) => source,
+                Err(e) => return Outcome::Failed(e),
+            };
+            drop(data);
+            for (position, index, key) in missing {
+                let rendition = job::process_image(&source, &self.job.variants[index]);
+                results[position] = Some(rendition.map(|r| Rendition { cache: key, ..r }));
+            }
+        }
+
+        let results = variants
+            .into_iter()
+            .zip(results)
+            .map(|(index, result)| (index, result.expect("todas as variantes foram processadas")))
+            .collect();
+        Outcome::Done(results)
+    }
+
+    /// Gera de novo uma variante que o manifesto dava como inalterada, mas
+    /// cuja saída foi ocupada ou mexida antes de chegar a vez dela de gravar.
+    fn render(&self, file: &InputFile, index: usize, key: Option<CacheKey>) -> Result<Rendition> {
+        let Some(header) = job::read_header(file, &self.limits)? else {
+            bail!("o arquivo não é mais uma imagem");
+        };
+        self.limits.check(&header)?;
+
+        let variant = &self.job.variants[index];
+        let rendition = if self.dry_run {
+            job::plan_image(&header, variant)
+        } else {
+            let _reservation = self.budget.reserve(header.peak_bytes([variant]));
+            let source = job::decode(header, None, &self.limits)?;
+            job::process_image(&source, variant)?
+        };
+        Ok(Rendition {
+            cache: key,
+            ..rendition
+        })
+    }
+}
+
+/// Grava as saídas na ordem dos arquivos e acumula o relatório.
+struct Writer {
+    layout: Layout,
+    collision: Collision,
+    overwrite: Overwrite,
+    /// Entradas da execução (canônicas), que nunca são sobrescritas
+    inputs: HashSet<PathBuf>,
+    claimed: HashSet<PathBuf>,
+    dry_run: bool,
+    counter: usize,
+    reports: Vec<ImageReport>,
+    /// Saídas a registrar no manifesto incremental
+    records: Vec<(CacheKey, ImageReport)>,
+}
+
+impl Writer {
+    fn finish(&mut self, file: &InputFile, outcome: Outcome, worker: &Worker) {
+        let path = &file.path;
+        match outcome {
+            Outcome::Ignored => println!("IGN -> {}", path.display()),
+            Outcome::Failed(e) => eprintln!("ERR -> {}: {e}", path.display()),
+            Outcome::Done(results) => {
+                for (index, result) in results {
+                    let result = match result {
+                        Ok(rendition)
+                            if rendition.report.action == Action::Unchanged
+                                && !self.is_current(&rendition.report) =>
+                        {
+                            worker.render(file, index, rendition.cache)
+                        }
+                        result => result,
+                    };
+                    let name = &worker.job.variants[index].name;
+                    match result.and_then(|rendition| self.write(rendition)) {
+                        Ok(report) if report.action == Action::Unchanged => {
+                            println!("OK  -> {} (inalterado)", report.output);
+                            self.reports.push(report);
+                        }
+                        Ok(report) => {
+                            let reason = report.reason.as_deref().unwrap_or_default();
+                            match report.action {
+                                Action::Skipped => println!("IGN -> {} ({reason})", report.output),
+                                Action::Refused => eprintln!("ERR -> {}: {reason}", path.display()),
+                                _ if self.dry_run => println!(
+                                    "DRY -> {} ({}, {}x{}, {})",
+                                    report.output,
+                                    report.new_format,
+                                    report.width,
+                                    report.height,
+                                    report.action.name()
+                                ),
+                                _ => println!("OK  -> {}", report.output),
+                            }
+                            self.reports.push(report);
+                        }
+                        Err(e) => match name {
+                            Some(name) => eprintln!("ERR -> {} [{name}]: {e}", path.display()),
+                            None => eprintln!("ERR -> {}: {e}", path.display()),
+                        },
+                    }
+                }
+            }
+        }
+    }
+
+    /// Diz se a saída que o manifesto dá como inalterada ainda vale: nenhuma
+    /// outra saída ocupou o caminho nesta execução e o arquivo continua com o
+    /// tamanho com que foi gravado. Como a gravação segue a ordem dos
+    /// arquivos, a decisão não depende de qual thread terminou antes.
+    fn is_current(&self, report: &ImageReport) -> bool {
+        let output = PathBuf::from(&report.output);
+        !self.claimed.contains(&output)
+            && fs::metadata(&output).is_ok_and(|m| m.len() == report.new_size)
+    }
+
+    /// Decide onde (e se) gravar uma saída e grava.
+    ///
+    /// Na ordem: colisões dentro da execução (`--on-collision`), recusa de
+    /// sobrescrever uma entrada e arquivos que já existiam (`--overwrite`).
+    /// A decisão vai para o relatório em `action`.
+    fn write(&mut self, rendition: Rendition) -> Result<ImageReport> {
+        self.counter += 1;
+
+        // Saída reaproveitada do manifesto: só reserva o caminho
+        if rendition.report.action == Action::Unchanged {
+            let report = rendition.report;
+            self.claimed.insert(PathBuf::from(&report.output));
+            if let Some(key) = rendition.cache {
+                self.records.push((key, report.clone()));
+            }
+            return Ok(report);
+        }
+
+        let planned = self.layout.path(&rendition, self.counter);
+        let Rendition {
+            file,
+            data,
+            mut report,
+            cache,
+            ..
+        } = rendition;
+        report.output = planned.display().to_string();
+
+        let mut action = Action::Created;
+        let mut path = planned;
+
+        if self.claimed.contains(&path) {
+            match self.collision {
+                Collision::Suffix => {
+                    path = self.free_path(&path);
+                    action = Action::Renamed;
+                }
+                Collision::Error => {
+                    report.action = Action::Refused;
+                    report.reason = Some(format!(
+                        "{} já foi gerado por outra entrada nesta execução (--on-collision)",
+                        path.display()
+                    ));
+                    return Ok(report);
+                }
+                Collision::Overwrite => action = Action::Overwritten,
+            }
+        }
+
+        if let Ok(existing) = path.canonicalize() {
+            if self.inputs.contains(&existing) {
+                report.action = Action::Refused;
+                report.reason = Some(format!(
+                    "{} é um arquivo de entrada e nunca é sobrescrito",
+                    path.display()
+                ));
+                return Ok(report);
+            }
+
+            if !self.claimed.contains(&path) {
+                let keep = match self.overwrite {
+                    Overwrite::Always => None,
+                    Overwrite::Never => Some("já existe; --overwrite never"),
+                    Overwrite::IfNewer if is_newer(file.disk_path(), &path)? => None,
+                    Overwrite::IfNewer => {
+                        Some("saída mais nova que a entrada; --overwrite if-newer")
+                    }
+                    Overwrite::Suffix => {
+                        path = self.free_path(&path);
+                        action = Action::Renamed;
+                        None
+                    }
+                };
+
+                if let Some(reason) = keep {
+                    report.output = path.display().to_string();
+                    report.new_size = fs::metadata(&path)?.len();
+                    report.action = Action::Skipped;
+                    report.reason = Some(reason.to_string());
+                    self.claimed.insert(path);
+                    return Ok(report);
+                }
+                if action == Action::Created {
+                    action = Action::Overwritten;
+                }
+            }
+        }
+
+        if let Some(data) = &data {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, data)?;
+        }
+
+        report.output = path.display().to_string();
+        report.action = action;
+        if let Some(key) = cache {
+            self.records.push((key, report.clone()));
+        }
+        self.claimed.insert(path);
+        Ok(report)
+    }
+
+    /// Primeiro `nome-N.ext` ainda não usado nesta execução nem existente no
+    /// disco (este último só com `--overwrite suffix`).
+    fn free_path(&self, path: &Path) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().map(|e| e.to_string_lossy());
+
+        (1..)
+            .map(|n| {
+                let name = match &extension {
+                    Some(ext) => format!("{stem}-{n}.{ext}"),
+                    None => format!("{stem}-{n}"),
+                };
+                path.with_file_name(name)
+            })
+            .find(|candidate| {
+                let on_disk = self.overwrite == Overwrite::Suffix && candidate.exists();
+                !self.claimed.contains(candidate) && !on_disk
+            })
+            .expect("sempre há um sufixo livre")
+    }
+}
+
+/// Diz se a entrada foi modificada depois da saída.
+fn is_newer(input: &Path, output: &Path) -> Result<bool> {
+    let input = fs::metadata(input)?.modified()?;
+    let output = fs::metadata(output)?.modified()?;
+    Ok(input > output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::OutputFormat;
+
+    fn writer(collision: Collision, overwrite: Overwrite, inputs: &[&Path]) -> Writer {
+        Writer {
+            layout: Layout {
+                flatten: false,
+                name: None,
+            },
+            collision,
+            overwrite,
+            inputs: inputs.iter().map(|p| p.canonicalize().unwrap()).collect(),
+            claimed: HashSet::new(),
+            dry_run: false,
+            counter: 0,
+            reports: Vec::new(),
+            records: Vec::new(),
+        }
+    }
+
+    /// PNG de `input` (gravado como `<stem>.png`) em `output`.
+    fn rendition(input: &Path, output: &Path, data: &[u8]) -> Rendition {
+        let file = InputFile {
+            path: input.to_path_buf(),
+            relative: PathBuf::from(input.file_name().unwrap()),
+            entry: None,
+        };
+        Rendition {
+            report: ImageReport {
+                input: file.path.display().to_string(),
+                output: String::new(),
+                variant: None,
+                original_format: "png".to_string(),
+                new_format: "png".to_string(),
+                original_size: 0,
+                new_size: data.len() as u64,
+                width: 1,
+                height: 1,
+                quality: None,
+                png: None,
+                jpeg: None,
+                action: Action::Created,
+                reason: None,
+                format: OutputFormat::Png,
+            },
+            file,
+            output: output.to_path_buf(),
+            preset: None,
+            data: Some(data.to_vec()),
+            cache: None,
+        }
+    }
+
+    #[test]
+    fn entrada_nunca_e_sobrescrita() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a.png");
+        fs::write(&input, "entrada").unwrap();
+
+        let mut writer = writer(Collision::Overwrite, Overwrite::Always, &[&input]);
+        let report = writer
+            .write(rendition(&input, dir.path(), b"saida"))
+            .unwrap();
+
+        assert_eq!(report.action, Action::Refused);
+        assert_eq!(fs::read_to_string(&input).unwrap(), "entrada");
+    }
+
+    #[test]
+    fn overwrite_never_mantem_o_arquivo_existente() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        fs::write(out.join("a.png"), "antigo").unwrap();
+
+        let mut writer = writer(Collision::Suffix, Overwrite::Never, &[]);
+        let report = writer
+            .write(rendition(&dir.path().join("a.png"), &out, b"novo"))
+            .unwrap();
+
+        assert_eq!(report.action, Action::Skipped);
+        assert_eq!(report.new_size, 6);
+        assert_eq!(fs::read_to_string(out.join("a.png")).unwrap(), "antigo");
+    }
+
+    #[test]
+    fn overwrite_suffix_pula_nomes_ocupados_no_disco() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        fs::write(out.join("a.png"), "antigo").unwrap();
+        fs::write(out.join("a-1.png"), "antigo").unwrap();
+
+        let mut writer = writer(Collision::Suffix, Overwrite::Suffix, &[]);
+        let report = writer
+            .write(rendition(&dir.path().join("a.png"), &out, b"novo"))
+            .unwrap();
+
+        assert_eq!(report.action, Action::Renamed);
+        assert_eq!(report.output, out.join("a-2.png").display().to_string());
+        assert_eq!(fs::read_to_string(out.join("a-2.png")).unwrap(), "novo");
+        assert_eq!(fs::read_to_string(out.join("a.png")).unwrap(), "antigo");
+    }
+
+    #[test]
+    fn colisao_com_sufixo_ou_erro() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let first = dir.path().join("x/a.png");
+        let second = dir.path().join("y/a.png");
+
+        let mut suffix = writer(Collision::Suffix, Overwrite::Always, &[]);
+        suffix.write(rendition(&first, &out, b"x")).unwrap();
+        let report = suffix.write(rendition(&second, &out, b"y")).unwrap();
+        assert_eq!(report.action, Action::Renamed);
+        assert_eq!(fs::read_to_string(out.join("a-1.png")).unwrap(), "y");
+
+        let mut error = writer(Collision::Error, Overwrite::Always, &[]);
+        error.write(rendition(&first, &out, b"x")).unwrap();
+        let report = error.write(rendition(&second, &out, b"y")).unwrap();
+        assert_eq!(report.action, Action::Refused);
+        assert_eq!(fs::read_to_string(out.join("a.png")).unwrap(), "x");
+    }
+
+    #[test]
+    fn colisao_com_overwrite_nao_ganha_sufixo() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let first = dir.path().join("x/a.png");
+        let second = dir.path().join("y/a.png");
+
+        // O arquivo gravado pela primeira entrada não conta como existente
+        let mut writer = writer(Collision::Overwrite, Overwrite::Suffix, &[]);
+        writer.write(rendition(&first, &out, b"x")).unwrap();
+        let report = writer.write(rendition(&second, &out, b"y")).unwrap();
+
+        assert_eq!(report.action, Action::Overwritten);
+        assert_eq!(report.output, out.join("a.png").display().to_string());
+        assert_eq!(fs::read_to_string(out.join("a.png")).unwrap(), "y");
+        assert!(!out.join("a-1.png").exists());
+    }
+
+    #[test]
+    fn saida_do_manifesto_so_vale_se_livre_e_intacta() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("a.png");
+        fs::write(&output, "saida").unwrap();
+
+        let mut report = rendition(&output, dir.path(), b"saida").report;
+        report.output = output.display().to_string();
+
+        let mut writer = writer(Collision::Suffix, Overwrite::Always, &[]);
+        assert!(writer.is_current(&report));
+
+        fs::write(&output, "editada").unwrap();
+        assert!(!writer.is_current(&report));
+
+        fs::write(&output, "saida").unwrap();
+        writer.claimed.insert(output);
+        assert!(!writer.is_current(&report));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::format::OutputFormat;
+use crate::job::{Action, ImageReport, InputFile, Job, Layout};
+
+/// Nome do manifesto gravado em cada diretório de saída.
+const MANIFEST: &str = ".img-tool-manifest.json";
+
+/// Manifesto de um diretório de saída: para cada entrada e configuração de
+/// variante, o hash do conteúdo da entrada e o relatório da saída gerada.
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    version: String,
+    entries: BTreeMap<String, BTreeMap<String, Entry>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Entry {
+    input_hash: String,
+    report: ImageReport,
+}
+
+/// Identifica uma saída no manifesto incremental.
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+    dir: PathBuf,
+    input: String,
+    settings: String,
+    input_hash: String,
+}
+
+/// Manifestos incrementais dos diretórios de saída de um job.
+pub struct Cache {
+    manifests: BTreeMap<PathBuf, Manifest>,
+    /// Hash das configurações efetivas de cada variante, na ordem do job
+    settings: Vec<String>,
+}
+
+impl Cache {
+    /// Lê os manifestos existentes. Manifestos de outra versão do img-tool
+    /// são descartados, já que o resultado da codificação pode ter mudado.
+    pub fn load(job: &Job, layout: &Layout) -> Result<Self> {
+        let mut manifests = BTreeMap::new();
+        for variant in &job.variants {
+            let path = variant.output.join(MANIFEST);
+            if manifests.contains_key(&variant.output) {
+                continue;
+            }
+            let manifest = match fs::read_to_string(&path) {
+                Ok(text) => serde_json::from_str::<Manifest>(&text)
+                    .ok()
+                    .filter(|m| m.version == env!("CARGO_PKG_VERSION"))
+                    .unwrap_or_default(),
+                Err(_) => Manifest::default(),
+            };
+            manifests.insert(variant.output.clone(), manifest);
+        }
+
+        let settings = job
+            .variants
+            .iter()
+            .map(|variant| {
+                let description = format!("{variant:?}\n{layout:?}");
+                blake3::hash(description.as_bytes()).to_hex().to_string()
+            })
+            .collect();
+
+        Ok(Cache {
+            manifests,
+            settings,
+        })
+    }
+
+    /// Calcula as chaves das variantes (pelos índices no job) de um arquivo.
+    ///
+    /// `data` é o conteúdo já lido de uma entrada de pacote; arquivos no
+    /// disco são lidos aos poucos.
+    pub fn keys(
+        &self,
+        file: &InputFile,
+        data: Option<&[u8]>,
+        job: &Job,
+        variants: &[usize],
+    ) -> Result<Vec<CacheKey>> {
+        let mut hasher = blake3::Hasher::new();
+        match data {
+            Some(data) => {
+                hasher.update(data);
+            }
+            None => {
+                hasher
+                    .update_reader(File::open(&file.path)?)
+                    .with_context(|| format!("não foi possível ler {}", file.path.display()))?;
+            }
+        }
+        let input_hash = hasher.finalize().to_hex().to_string();
+
+        Ok(variants
+            .iter()
+            .map(|&index| CacheKey {
+                dir: job.variants[index].output.clone(),
+                input: file.path.display().to_string(),
+                settings: self.settings[index].clone(),
+                input_hash: input_hash.clone(),
+            })
+            .collect())
+    }
+
+    /// Relatório da última execução, se a saída ainda está lá e nada mudou.
+    ///
+    /// Quem grava confere de novo, na ordem dos arquivos, se a saída não foi
+    /// ocupada por outra entrada nesta mesma execução.
+    pub fn lookup(&self, key: &CacheKey) -> Option<ImageReport> {
+        let entry = self
+            .manifests
+            .get(&key.dir)?
+            .entries
+            .get(&key.input)?
+            .get(&key.settings)?;
+        if entry.input_hash != key.input_hash {
+            return None;
+        }
+
+        // A saída pode ter sido apagada ou editada desde então
+        let size = fs::metadata(&entry.report.output).ok()?.len();
+        if size != entry.report.new_size {
+            return None;
+        }
+
+        let mut report = entry.report.clone();
+        report.format = OutputFormat::from_str(&report.new_format, true).ok()?;
+        report.action = Action::Unchanged;
+        report.reason = None;
+        Some(report)
+    }
+
+    /// Registra as saídas desta execução e grava os manifestos.
+    pub fn save(mut self, records: Vec<(CacheKey, ImageReport)>) -> Result<()> {
+        for (key, report) in records {
+            let manifest = self.manifests.entry(key.dir).or_default();
+            manifest.entries.entry(key.input).or_default().insert(
+                key.settings,
+                Entry {
+                    input_hash: key.input_hash,
+                    report,
+                },
+            );
+        }
+
+        for (dir, mut manifest) in self.manifests {
+            manifest.version = env!("CARGO_PKG_VERSION").to_string();
+            let path: &Path = &dir.join(MANIFEST);
+            fs::write(path, serde_json::to_string_pretty(&manifest)?)
+                .with_context(|| format!("não foi possível gravar {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use image::Rgba;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::format::{EncodeOptions, OutputFormat};
+use crate::job::{InputFile, Job, Variant};
+use crate::resize::{self, ResizeOptions};
+use crate::walk::{WalkOptions, collect_paths, glob_set};
+use crate::{ops, target_size};
+
+/// Configuração de pipeline já resolvida para um preset.
+///
+/// Exemplo em TOML:
+///
+/// ```toml
+/// inputs = ["assets"]
+/// output = "dist"
+/// include = ["**/*.png", "**/*.jpg"]
+/// format = ["webp", "jpg"]
+/// widths = [640, 1280]
+/// operations = ["resize=1600x>"]
+///
+/// [encode]
+/// webp_quality = 82
+///
+/// [presets.thumb]
+/// format = "jpeg"
+/// operations = ["resize=320x320,mode=cover"]
+/// encode = { jpeg_quality = 70, jpeg_progressive = true }
+/// ```
+///
+/// Os campos de um preset sobrescrevem os de nível superior; tabelas como
+/// `encode` e `resize` são mescladas campo a campo.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    #[serde(default)]
+    pub inputs: Vec<PathBuf>,
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub report: Option<PathBuf>,
+    #[serde(default, deserialize_with = "value_enum_list")]
+    pub format: Vec<OutputFormat>,
+    #[serde(default, deserialize_with = "widths")]
+    pub widths: Vec<u32>,
+    #[serde(default)]
+    pub operations: Vec<String>,
+    #[serde(default, deserialize_with = "byte_size_opt")]
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub encode: EncodeOptions,
+    #[serde(default)]
+    pub resize: ResizeOptions,
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from("output")
+}
+
+/// Arquivo de pipeline lido, ainda sem preset aplicado.
+pub struct ConfigFile {
+    path: PathBuf,
+    root: Value,
+    presets: Value,
+}
+
+/// Lê o arquivo de pipeline (TOML, JSON ou YAML, pela extensão).
+pub fn load(path: &Path) -> Result<ConfigFile> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("não foi possível ler {}", path.display()))?;
+
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let mut root: Value = match extension.as_deref() {
+        Some("toml") => toml::from_str(&text)?,
+        Some("json") => serde_json::from_str(&text)?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&text)?,
+        _ => bail!(
+            "formato de configuração não reconhecido: {} (use .toml, .json ou .yaml)",
+            path.display()
+        ),
+    };
+
+    let Some(table) = root.as_object_mut() else {
+        bail!("{}: a configuração precisa ser uma tabela", path.display());
+    };
+    let presets = table.remove("presets").unwrap_or(Value::Null);
+
+    Ok(ConfigFile {
+        path: path.to_path_buf(),
+        root,
+        presets,
+    })
+}
+
+impl ConfigFile {
+    /// Monta o job com os presets escolhidos, cada um virando uma ou mais
+    /// variantes. Sem presets, usa as configurações de nível superior.
+    ///
+    /// Entradas, filtros e relatório vêm do primeiro preset (ou do nível
+    /// superior), já que todos compartilham a mesma decodificação. Com mais de
+    /// um preset, o nome de cada um entra no nome dos arquivos gerados.
+    pub fn job(
+        &self,
+        presets: &[String],
+        walk: &WalkOptions,
+    ) -> Result<(Vec<InputFile>, Option<PathBuf>, Job)> {
+        let configs = if presets.is_empty() {
+            vec![(None, self.resolve(None)?)]
+        } else {
+            presets
+                .iter()
+                .map(|name| Ok((Some(name.as_str()), self.resolve(Some(name))?)))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let paths = configs[0].1.collect_paths(walk)?;
+        let report = configs[0].1.report.clone();
+
+        let mut variants = Vec::new();
+        for (preset, config) in configs {
+            variants.extend(config.variants(preset, presets.len() > 1)?);
+        }
+
+        Ok((paths, report, Job { variants }))
+    }
+
+    /// Aplica o preset sobre as configurações de nível superior.
+    ///
+    /// Caminhos relativos no arquivo são resolvidos a partir do diretório dele.
+    fn resolve(&self, preset: Option<&str>) -> Result<PipelineConfig> {
+        let mut root = self.root.clone();
+
+        if let Some(name) = preset {
+            let Some(overrides) = self.presets.get(name) else {
+                let known: Vec<_> = self
+                    .presets
+                    .as_object()
+                    .map(|p| p.keys().map(String::as_str).collect())
+                    .unwrap_or_default();
+                bail!(
+                    "preset não encontrado: {name} (disponíveis: {})",
+                    known.join(", ")
+                );
+            };
+            merge(&mut root, overrides.clone());
+        }
+
+        let mut config: PipelineConfig = serde_json::from_value(root)
+            .with_context(|| format!("configuração inválida em {}", self.path.display()))?;
+        config
+            .encode
+            .validate()
+            .with_context(|| format!("configuração inválida em {}", self.path.display()))?;
+
+        let base = self.path.parent().unwrap_or(Path::new(""));
+        for input in &mut config.inputs {
+            *input = base.join(&*input);
+        }
+        config.output = base.join(&config.output);
+        if let Some(report) = &mut config.report {
+            *report = base.join(&*report);
+        }
+
+        Ok(config)
+    }
+}
+
+impl PipelineConfig {
+    /// Coleta os arquivos de todas as entradas, aplicando include/exclude.
+    ///
+    /// Os padrões são comparados com o caminho relativo à entrada, depois dos
+    /// filtros da linha de comando.
+    pub fn collect_paths(&self, walk: &WalkOptions) -> Result<Vec<InputFile>> {
+        if self.inputs.is_empty() {
+            bail!("a configuração não define nenhuma entrada em `inputs`");
+        }
+
+        let include = glob_set(&self.include)?;
+        let exclude = glob_set(&self.exclude)?;
+
+        let mut files = Vec::new();
+        for input in &self.inputs {
+            for file in collect_paths(input, walk)? {
+                let included = self.include.is_empty() || include.is_match(&file.relative);
+                if included && !exclude.is_match(&file.relative) {
+                    files.push(file);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Variantes descritas por esta configuração; com `label`, o nome do
+    /// preset entra no nome da variante.
+    fn variants(&self, preset: Option<&str>, label: bool) -> Result<Vec<Variant>> {
+        let pipeline = self
+            .operations
+            .iter()
+            .map(|spec| ops::parse_op(spec, &self.resize))
+            .collect::<Result<_>>()?;
+
+        let variant = Variant {
+            name: preset.filter(|_| label).map(str::to_string),
+            preset: preset.map(str::to_string),
+            output: self.output.clone(),
+            format: None,
+            encode: self.encode.clone(),
+            resize_opts: self.resize.clone(),
+            max_bytes: self.max_bytes,
+            pipeline,
+            width: None,
+            previous_width: None,
+        };
+
+        Ok(variant.expand(&self.widths, &self.format))
+    }
+}
+
+/// Mescla `overrides` sobre `base`; objetos são mesclados recursivamente e
+/// qualquer outro valor é substituído.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
+/// Lê um enum da linha de comando (aceitando os mesmos nomes e apelidos).
+pub fn value_enum<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ValueEnum,
+{
+    let s = String::deserialize(deserializer)?;
+    T::from_str(&s, true).map_err(serde::de::Error::custom)
+}
+
+/// Lê um enum ou uma lista deles (ex: `format = "webp"` ou `["webp", "jpg"]`).
+fn value_enum_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ValueEnum,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let names = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(name) => vec![name],
+        OneOrMany::Many(names) => names,
+    };
+    names
+        .iter()
+        .map(|name| T::from_str(name, true).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Lê as larguras de `widths`, que como em --widths precisam ser maiores que zero.
+fn widths<'de, D>(deserializer: D) -> Result<Vec<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let widths = Vec::<u32>::deserialize(deserializer)?;
+    if widths.contains(&0) {
+        return Err(serde::de::Error::custom(
+            "as larguras em `widths` precisam ser maiores que zero",
+        ));
+    }
+    Ok(widths)
+}
+
+/// Lê uma cor no mesmo formato de --background.
+pub fn color<'de, D>(deserializer: D) -> Result<Rgba<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    resize::parse_color(&s).map_err(serde::de::Error::custom)
+}
+
+/// Lê um tamanho em bytes como número ou texto ("200KB").
+fn byte_size_opt<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(0) => Err(serde::de::Error::custom(
+            "o tamanho precisa ser maior que zero",
+        )),
+        Size::Bytes(bytes) => Ok(Some(bytes)),
+        Size::Text(text) => target_size::parse_byte_size(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
@@ -0,0 +1,571 @@
+use std::io::Cursor;
+
+use anyhow::{Result, bail};
+use clap::{Args, Parser, ValueEnum};
+use image::codecs::hdr::HdrEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
+use image::codecs::pnm::{PnmSubtype, SampleEncoding};
+use image::{DynamicImage, ImageEncoder, ImageFormat, ImageOutputFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+/// Formatos que o img-tool sabe gravar.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    #[value(alias = "jpg", alias = "jpe", alias = "jfif")]
+    Jpeg,
+    Webp,
+    Avif,
+    #[value(alias = "tif")]
+    Tiff,
+    #[value(alias = "dib")]
+    Bmp,
+    Gif,
+    Ico,
+    #[value(alias = "icb", alias = "vda", alias = "vst")]
+    Tga,
+    Qoi,
+    /// PNM em tons de cinza
+    Pgm,
+    /// PNM colorido
+    #[value(alias = "pnm")]
+    Ppm,
+    /// PNM arbitrário (mantém transparência)
+    Pam,
+    #[value(name = "exr", alias = "openexr")]
+    OpenExr,
+    #[value(alias = "rgbe")]
+    Hdr,
+    #[value(name = "ff", alias = "farbfeld")]
+    Farbfeld,
+}
+
+impl OutputFormat {
+    /// Formato padrão de saída quando o usuário não especifica um.
+    pub fn default_for(input_format: ImageFormat) -> Self {
+        match input_format {
+            ImageFormat::Png => OutputFormat::Jpeg,
+            ImageFormat::Jpeg => OutputFormat::Png,
+            _ => OutputFormat::Png,
+        }
+    }
+
+    /// Extensão usada no arquivo de saída.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Avif => "avif",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Ico => "ico",
+            OutputFormat::Tga => "tga",
+            OutputFormat::Qoi => "qoi",
+            OutputFormat::Pgm => "pgm",
+            OutputFormat::Ppm => "ppm",
+            OutputFormat::Pam => "pam",
+            OutputFormat::OpenExr => "exr",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Farbfeld => "ff",
+        }
+    }
+
+    /// Nome do formato como aparece no relatório.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::OpenExr => "openexr",
+            OutputFormat::Farbfeld => "farbfeld",
+            other => other.extension(),
+        }
+    }
+
+    /// Tipo MIME, usado no atributo `type` do `<source>` em HTML.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+            OutputFormat::Avif => "image/avif",
+            OutputFormat::Tiff => "image/tiff",
+            OutputFormat::Bmp => "image/bmp",
+            OutputFormat::Gif => "image/gif",
+            OutputFormat::Ico => "image/x-icon",
+            OutputFormat::Tga => "image/x-tga",
+            OutputFormat::Qoi => "image/qoi",
+            OutputFormat::Pgm | OutputFormat::Ppm | OutputFormat::Pam => "image/x-portable-anymap",
+            OutputFormat::OpenExr => "image/x-exr",
+            OutputFormat::Hdr => "image/vnd.radiance",
+            OutputFormat::Farbfeld => "image/x-farbfeld",
+        }
+    }
+}
+
+/// Opções dos encoders, compartilhadas por todos os formatos de saída.
+///
+/// Nos arquivos de configuração os campos usam os mesmos nomes das opções
+/// da linha de comando, com `_` no lugar de `-`.
+#[derive(Args, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncodeOptions {
+    /// Qualidade do JPEG (1 a 100)
+    #[arg(long, default_value_t = 75, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub jpeg_quality: u8,
+
+    /// Gera JPEG progressivo em vez de baseline
+    #[arg(long)]
+    pub jpeg_progressive: bool,
+
+    /// Subamostragem de croma do JPEG
+    #[arg(long, value_enum, default_value_t = ChromaSubsampling::S420)]
+    #[serde(deserialize_with = "config::value_enum")]
+    pub jpeg_subsampling: ChromaSubsampling,
+
+    /// Nível de compressão do PNG
+    #[arg(long, value_enum, default_value_t = PngCompression::Default)]
+    #[serde(deserialize_with = "config::value_enum")]
+    pub png_compression: PngCompression,
+
+    /// Filtro de linhas do PNG
+    #[arg(long, value_enum, default_value_t = PngFilter::Adaptive)]
+    #[serde(deserialize_with = "config::value_enum")]
+    pub png_filter: PngFilter,
+
+    /// Otimiza o PNG sem perdas depois de codificar (nível 0 a 6, padrão 2):
+    /// testa filtros e estratégias de deflate e reduz profundidade de bits e
+    /// paleta. O nível vai depois de `=` (--png-optimize=4)
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "2",
+        value_parser = clap::value_parser!(u8).range(0..=6)
+    )]
+    pub png_optimize: Option<u8>,
+
+    /// Qualidade do WebP com perdas (0 a 100)
+    #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub webp_quality: u8,
+
+    /// Gera WebP sem perdas (ignora --webp-quality)
+    #[arg(long)]
+    pub webp_lossless: bool,
+
+    /// Qualidade do AVIF (1 a 100)
+    #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub avif_quality: u8,
+
+    /// Velocidade do encoder AVIF (1 = mais lento e menor, 10 = mais rápido)
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..=10))]
+    pub avif_speed: u8,
+
+    /// Qualidade do canal alfa no AVIF (padrão: igual a --avif-quality)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub avif_alpha_quality: Option<u8>,
+
+    /// Tratamento da transparência no AVIF
+    #[arg(long, value_enum, default_value_t = AvifAlpha::Clean)]
+    #[serde(deserialize_with = "config::value_enum")]
+    pub avif_alpha: AvifAlpha,
+}
+
+impl Default for EncodeOptions {
+    /// Os mesmos valores padrão da linha de comando.
+    fn default() -> Self {
+        #[derive(Parser)]
+        struct Defaults {
+            #[command(flatten)]
+            opts: EncodeOptions,
+        }
+        Defaults::parse_from(["img-tool"]).opts
+    }
+}
+
+impl EncodeOptions {
+    /// Confere as faixas que a linha de comando já garante, para opções
+    /// vindas de um arquivo de configuração.
+    pub fn validate(&self) -> Result<()> {
+        let ranges = [
+            ("jpeg_quality", Some(self.jpeg_quality), 1..=100),
+            ("png_optimize", self.png_optimize, 0..=6),
+            ("webp_quality", Some(self.webp_quality), 0..=100),
+            ("avif_quality", Some(self.avif_quality), 1..=100),
+            ("avif_speed", Some(self.avif_speed), 1..=10),
+            ("avif_alpha_quality", self.avif_alpha_quality, 1..=100),
+        ];
+        for (name, value, range) in ranges {
+            if let Some(value) = value
+                && !range.contains(&value)
+            {
+                bail!(
+                    "`{name}` precisa estar entre {} e {} (veio {value})",
+                    range.start(),
+                    range.end()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Qualidade usada pelo encoder do formato, se ele tiver esse ajuste.
+    pub fn quality(&self, format: OutputFormat) -> Option<u8> {
+        match format {
+            OutputFormat::Jpeg => Some(self.jpeg_quality),
+            OutputFormat::Webp if !self.webp_lossless => Some(self.webp_quality),
+            OutputFormat::Avif => Some(self.avif_quality),
+            _ => None,
+        }
+    }
+
+    /// Cópia das opções com outra qualidade para o encoder do formato.
+    pub fn with_quality(&self, format: OutputFormat, quality: u8) -> EncodeOptions {
+        let mut opts = self.clone();
+        match format {
+            OutputFormat::Jpeg => opts.jpeg_quality = quality,
+            OutputFormat::Webp => opts.webp_quality = quality,
+            OutputFormat::Avif => opts.avif_quality = quality,
+            _ => {}
+        }
+        opts
+    }
+
+    /// Configuração efetiva do PNG, para registrar no relatório.
+    pub fn png_settings(&self) -> PngSettings {
+        PngSettings {
+            compression: self.png_compression,
+            filter: self.png_filter,
+            optimize: self.png_optimize,
+        }
+    }
+
+    /// Configuração efetiva do JPEG, para registrar no relatório.
+    pub fn jpeg_settings(&self) -> JpegSettings {
+        JpegSettings {
+            quality: self.jpeg_quality,
+            progressive: self.jpeg_progressive,
+            subsampling: self.jpeg_subsampling,
+        }
+    }
+}
+
+/// Nível de compressão do deflate no PNG.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PngCompression {
+    Fast,
+    Default,
+    Best,
+}
+
+/// Filtro aplicado às linhas antes da compressão do PNG.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Escolhe o melhor filtro para cada linha
+    Adaptive,
+}
+
+/// Parâmetros usados para gerar um PNG.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PngSettings {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optimize: Option<u8>,
+}
+
+/// Subamostragem de croma suportada na saída JPEG.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum ChromaSubsampling {
+    /// Sem subamostragem (melhor para texto e bordas coloridas)
+    #[value(name = "444", alias = "4:4:4")]
+    #[serde(rename = "4:4:4")]
+    S444,
+    /// Metade da resolução de croma na horizontal
+    #[value(name = "422", alias = "4:2:2")]
+    #[serde(rename = "4:2:2")]
+    S422,
+    /// Metade da resolução de croma nas duas direções (menor arquivo)
+    #[value(name = "420", alias = "4:2:0")]
+    #[serde(rename = "4:2:0")]
+    S420,
+}
+
+/// Parâmetros usados para gerar um JPEG.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct JpegSettings {
+    pub quality: u8,
+    pub progressive: bool,
+    pub subsampling: ChromaSubsampling,
+}
+
+/// Como o canal alfa é gravado no AVIF.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum AvifAlpha {
+    /// Limpa a cor dos pixels totalmente transparentes (comprime melhor)
+    Clean,
+    /// Mantém a cor dos pixels transparentes intacta
+    Dirty,
+    /// Grava as cores pré-multiplicadas pelo alfa
+    Premultiplied,
+    /// Descarta a transparência e grava só RGB
+    Discard,
+}
+
+/// Codifica a imagem no formato pedido e devolve os bytes do arquivo.
+///
+/// Cada encoder aceita um conjunto limitado de tipos de cor, então a imagem é
+/// convertida antes quando necessário.
+pub fn encode(img: &DynamicImage, format: OutputFormat, opts: &EncodeOptions) -> Result<Vec<u8>> {
+    let mut buf = Cursor::new(Vec::new());
+
+    match format {
+        OutputFormat::Png => return encode_png(img, opts.png_settings()),
+        OutputFormat::Jpeg => return encode_jpeg(img, opts.jpeg_settings()),
+        OutputFormat::Webp => return encode_webp(img, opts.webp_quality, opts.webp_lossless),
+        OutputFormat::Avif => return encode_avif(img, opts),
+        OutputFormat::Tiff => {
+            let img = match img {
+                DynamicImage::ImageLumaA8(_) => DynamicImage::ImageRgba8(img.to_rgba8()),
+                DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgba32F(_) => {
+                    DynamicImage::ImageRgba16(img.to_rgba16())
+                }
+                DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb16(img.to_rgb16()),
+                _ => img.clone(),
+            };
+            img.write_to(&mut buf, ImageOutputFormat::Tiff)?;
+        }
+        OutputFormat::Bmp | OutputFormat::Tga => {
+            let output = if format == OutputFormat::Bmp {
+                ImageOutputFormat::Bmp
+            } else {
+                ImageOutputFormat::Tga
+            };
+            to_8bit(img).write_to(&mut buf, output)?;
+        }
+        OutputFormat::Gif => {
+            img.write_to(&mut buf, ImageOutputFormat::Gif)?;
+        }
+        OutputFormat::Ico => {
+            if img.width() > 256 || img.height() > 256 {
+                bail!(
+                    "ICO suporta no máximo 256x256 (imagem tem {}x{}); use --resize",
+                    img.width(),
+                    img.height()
+                );
+            }
+            to_8bit(img).write_to(&mut buf, ImageOutputFormat::Ico)?;
+        }
+        OutputFormat::Qoi => {
+            let img = if img.color().has_alpha() {
+                DynamicImage::ImageRgba8(img.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(img.to_rgb8())
+            };
+            img.write_to(&mut buf, ImageOutputFormat::Qoi)?;
+        }
+        OutputFormat::Pgm => {
+            DynamicImage::ImageLuma8(img.to_luma8()).write_to(
+                &mut buf,
+                ImageOutputFormat::Pnm(PnmSubtype::Graymap(SampleEncoding::Binary)),
+            )?;
+        }
+        OutputFormat::Ppm => {
+            DynamicImage::ImageRgb8(img.to_rgb8()).write_to(
+                &mut buf,
+                ImageOutputFormat::Pnm(PnmSubtype::Pixmap(SampleEncoding::Binary)),
+            )?;
+        }
+        OutputFormat::Pam => {
+            to_8bit(img).write_to(&mut buf, ImageOutputFormat::Pnm(PnmSubtype::ArbitraryMap))?;
+        }
+        OutputFormat::OpenExr => {
+            let img = if img.color().has_alpha() {
+                DynamicImage::ImageRgba32F(img.to_rgba32f())
+            } else {
+                DynamicImage::ImageRgb32F(img.to_rgb32f())
+            };
+            img.write_to(&mut buf, ImageOutputFormat::OpenExr)?;
+        }
+        OutputFormat::Hdr => {
+            // O HDR não passa pelo write_to, só tem encoder próprio
+            let rgb = img.to_rgb32f();
+            let pixels: Vec<_> = rgb.pixels().copied().collect();
+            HdrEncoder::new(&mut buf).encode(
+                &pixels,
+                rgb.width() as usize,
+                rgb.height() as usize,
+            )?;
+        }
+        OutputFormat::Farbfeld => {
+            DynamicImage::ImageRgba16(img.to_rgba16())
+                .write_to(&mut buf, ImageOutputFormat::Farbfeld)?;
+        }
+    }
+
+    Ok(buf.into_inner())
+}
+
+/// Reduz a imagem para 8 bits por canal, mantendo cinza/cor e transparência.
+fn to_8bit(img: &DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => img.clone(),
+        DynamicImage::ImageLuma16(_) => DynamicImage::ImageLuma8(img.to_luma8()),
+        DynamicImage::ImageLumaA16(_) => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+        _ if img.color().has_alpha() => DynamicImage::ImageRgba8(img.to_rgba8()),
+        _ => DynamicImage::ImageRgb8(img.to_rgb8()),
+    }
+}
+
+/// Codifica a imagem em PNG e, se pedido, passa o resultado pelo oxipng.
+fn encode_png(img: &DynamicImage, settings: PngSettings) -> Result<Vec<u8>> {
+    let img = match img {
+        DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb16(img.to_rgb16()),
+        DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgba16(img.to_rgba16()),
+        _ => img.clone(),
+    };
+
+    let compression = match settings.compression {
+        PngCompression::Fast => CompressionType::Fast,
+        PngCompression::Default => CompressionType::Default,
+        PngCompression::Best => CompressionType::Best,
+    };
+    let filter = match settings.filter {
+        PngFilter::None => PngFilterType::NoFilter,
+        PngFilter::Sub => PngFilterType::Sub,
+        PngFilter::Up => PngFilterType::Up,
+        PngFilter::Avg => PngFilterType::Avg,
+        PngFilter::Paeth => PngFilterType::Paeth,
+        PngFilter::Adaptive => PngFilterType::Adaptive,
+    };
+
+    let mut data = Vec::new();
+    PngEncoder::new_with_quality(&mut data, compression, filter).write_image(
+        img.as_bytes(),
+        img.width(),
+        img.height(),
+        img.color(),
+    )?;
+
+    if let Some(level) = settings.optimize {
+        let optimized = oxipng::optimize_from_memory(&data, &oxipng::Options::from_preset(level))?;
+        // Só troca pelo resultado otimizado quando ele for realmente menor
+        if optimized.len() < data.len() {
+            data = optimized;
+        }
+    }
+
+    Ok(data)
+}
+
+/// Codifica a imagem em JPEG com o jpeg-encoder, que permite escolher modo
+/// progressivo e subamostragem de croma.
+fn encode_jpeg(img: &DynamicImage, settings: JpegSettings) -> Result<Vec<u8>> {
+    let (Ok(width), Ok(height)) = (u16::try_from(img.width()), u16::try_from(img.height())) else {
+        bail!(
+            "JPEG suporta no máximo 65535x65535 (imagem tem {}x{})",
+            img.width(),
+            img.height()
+        );
+    };
+
+    let mut data = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut data, settings.quality);
+    encoder.set_progressive(settings.progressive);
+    encoder.set_sampling_factor(match settings.subsampling {
+        ChromaSubsampling::S444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+        ChromaSubsampling::S422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+        ChromaSubsampling::S420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+    });
+
+    // JPEG não tem alfa nem 16 bits
+    if img.color().has_color() {
+        let rgb = img.to_rgb8();
+        encoder.encode(&rgb, width, height, jpeg_encoder::ColorType::Rgb)?;
+    } else {
+        let luma = img.to_luma8();
+        encoder.encode(&luma, width, height, jpeg_encoder::ColorType::Luma)?;
+    }
+
+    Ok(data)
+}
+
+/// Maior largura ou altura que a libwebp aceita.
+const WEBP_MAX_DIMENSION: u32 = 16383;
+
+/// Codifica a imagem em WebP usando a libwebp, com ou sem perdas.
+fn encode_webp(img: &DynamicImage, quality: u8, lossless: bool) -> Result<Vec<u8>> {
+    let (width, height) = (img.width(), img.height());
+    if width == 0 || height == 0 || width > WEBP_MAX_DIMENSION || height > WEBP_MAX_DIMENSION {
+        bail!(
+            "WebP suporta de 1x1 a {WEBP_MAX_DIMENSION}x{WEBP_MAX_DIMENSION} (imagem tem {width}x{height})"
+        );
+    }
+
+    // A libwebp só aceita RGB/RGBA de 8 bits; sem perdas, a qualidade vira o
+    // esforço de compressão e fica no padrão da libwebp
+    let quality = if lossless { 75.0 } else { f32::from(quality) };
+    let memory = if img.color().has_alpha() {
+        let rgba = img.to_rgba8();
+        webp::Encoder::from_rgba(&rgba, width, height).encode_simple(lossless, quality)
+    } else {
+        let rgb = img.to_rgb8();
+        webp::Encoder::from_rgb(&rgb, width, height).encode_simple(lossless, quality)
+    };
+    match memory {
+        Ok(memory) => Ok(memory.to_vec()),
+        Err(e) => bail!("a libwebp não conseguiu codificar a imagem ({e:?})"),
+    }
+}
+
+/// Codifica a imagem em AVIF usando o ravif, respeitando qualidade, velocidade
+/// e o tratamento de transparência escolhidos.
+fn encode_avif(img: &DynamicImage, opts: &EncodeOptions) -> Result<Vec<u8>> {
+    let alpha_mode = match opts.avif_alpha {
+        AvifAlpha::Clean | AvifAlpha::Discard => ravif::AlphaColorMode::UnassociatedClean,
+        AvifAlpha::Dirty => ravif::AlphaColorMode::UnassociatedDirty,
+        AvifAlpha::Premultiplied => ravif::AlphaColorMode::Premultiplied,
+    };
+
+    let encoder = ravif::Encoder::new()
+        .with_quality(f32::from(opts.avif_quality))
+        .with_alpha_quality(f32::from(
+            opts.avif_alpha_quality.unwrap_or(opts.avif_quality),
+        ))
+        .with_speed(opts.avif_speed)
+        .with_alpha_color_mode(alpha_mode);
+
+    let width = img.width() as usize;
+    let height = img.height() as usize;
+
+    let encoded = if img.color().has_alpha() && !matches!(opts.avif_alpha, AvifAlpha::Discard) {
+        let pixels: Vec<ravif::RGBA8> = img
+            .to_rgba8()
+            .pixels()
+            .map(|p| ravif::RGBA8::new(p[0], p[1], p[2], p[3]))
+            .collect();
+        encoder.encode_rgba(ravif::Img::new(&pixels[..], width, height))?
+    } else {
+        let pixels: Vec<ravif::RGB8> = img
+            .to_rgb8()
+            .pixels()
+            .map(|p| ravif::RGB8::new(p[0], p[1], p[2]))
+            .collect();
+        encoder.encode_rgb(ravif::Img::new(&pixels[..], width, height))?
+    };
+
+    Ok(encoded.avif_file)
+}
@@ -0,0 +1,669 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+
+use anyhow::{Result, bail};
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::Entry;
+use crate::cache::CacheKey;
+use crate::format::{self, EncodeOptions, JpegSettings, OutputFormat, PngSettings};
+use crate::naming::{NameFields, NameTemplate};
+use crate::ops::{self, Operation};
+use crate::resize::{Geometry, ResizeOptions};
+use crate::target_size;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImageReport {
+    pub input: String,
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    pub original_format: String,
+    pub new_format: String,
+    pub original_size: u64,
+    pub new_size: u64,
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub png: Option<PngSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jpeg: Option<JpegSettings>,
+    /// O que foi feito com o arquivo de saída
+    pub action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip)]
+    pub format: OutputFormat,
+}
+
+/// Decisão tomada para um arquivo de saída.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// Arquivo novo
+    Created,
+    /// Substituiu um arquivo existente
+    Overwritten,
+    /// Gravado com sufixo porque o nome já estava em uso
+    Renamed,
+    /// Não gravado porque o arquivo existente foi mantido
+    Skipped,
+    /// Não gravado porque sobrescreveria uma entrada ou outra saída
+    Refused,
+    /// Não regerado: entrada e configurações iguais às da última execução
+    Unchanged,
+}
+
+impl Action {
+    /// Nome como aparece no relatório.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Created => "created",
+            Action::Overwritten => "overwritten",
+            Action::Renamed => "renamed",
+            Action::Skipped => "skipped",
+            Action::Refused => "refused",
+            Action::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// Uma das saídas geradas para cada entrada: para onde gravar, em que
+/// formato e quais operações aplicar.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    /// Nome usado no arquivo de saída e no relatório (ex: "thumb", "640w")
+    pub name: Option<String>,
+    /// Preset do arquivo de configuração que gerou a variante
+    pub preset: Option<String>,
+    pub output: PathBuf,
+    pub format: Option<OutputFormat>,
+    pub encode: EncodeOptions,
+    pub resize_opts: ResizeOptions,
+    pub max_bytes: Option<u64>,
+    pub pipeline: Vec<Arc<dyn Operation>>,
+    /// Largura pedida em `widths` e a largura anterior da mesma escada
+    pub width: Option<u32>,
+    pub previous_width: Option<u32>,
+}
+
+impl Variant {
+    /// Desdobra a variante em uma por combinação de largura e formato.
+    ///
+    /// Cada largura vira um resize final que só reduz (`LARGURAx>`) e entra no
+    /// nome da variante; sem larguras nem formatos, devolve a própria variante.
+    pub fn expand(self, widths: &[u32], formats: &[OutputFormat]) -> Vec<Variant> {
+        let mut sorted = widths.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let widths: Vec<(Option<u32>, Option<u32>)> = if sorted.is_empty() {
+            vec![(None, None)]
+        } else {
+            sorted
+                .iter()
+                .enumerate()
+                .map(|(i, &w)| (Some(w), i.checked_sub(1).map(|p| sorted[p])))
+                .collect()
+        };
+        let formats: Vec<Option<OutputFormat>> = if formats.is_empty() {
+            vec![self.format]
+        } else {
+            formats.iter().copied().map(Some).collect()
+        };
+
+        let mut variants = Vec::new();
+        for &(width, previous) in &widths {
+            for format in &formats {
+                let mut variant = self.clone();
+                variant.format = *format;
+                variant.width = width;
+                variant.previous_width = previous;
+                if let Some(width) = width {
+                    variant.pipeline.push(Arc::new(ops::Resize::new(
+                        Geometry::max_width(width),
+                        self.resize_opts.clone(),
+                    )));
+                    variant.name = Some(match &self.name {
+                        Some(name) => format!("{name}-{width}w"),
+                        None => format!("{width}w"),
+                    });
+                }
+                variants.push(variant);
+            }
+        }
+        variants
+    }
+
+    /// Diz se a variante deve ser gerada para uma imagem com estas dimensões.
+    ///
+    /// Larguras maiores que a imagem que sai do pipeline não a ampliam, então
+    /// só a primeira delas é gerada (com a largura que a imagem já tem); as
+    /// demais seriam cópias idênticas.
+    pub fn applies_to(&self, width: u32, height: u32) -> bool {
+        let (mut width, mut height) = (width, height);
+        for op in &self.pipeline {
+            (width, height) = op.output_size(width, height);
+        }
+        self.previous_width.is_none_or(|p| p < width)
+    }
+
+    /// Nome da variante para uma saída com esta largura: uma imagem mais
+    /// estreita que a largura pedida leva no nome a largura que tem de verdade
+    /// (`a-64w.webp`, não `a-320w.webp`).
+    fn name_for(&self, width: u32) -> Option<String> {
+        let name = self.name.as_ref()?;
+        match self.width {
+            Some(requested) if width < requested => {
+                let label = name.strip_suffix(&format!("{requested}w")).unwrap_or(name);
+                Some(format!("{label}{width}w"))
+            }
+            _ => Some(name.clone()),
+        }
+    }
+
+    /// Memória das cópias feitas ao gerar a variante, medida pela maior
+    /// imagem intermediária do pipeline: entrada e saída de cada operação, o
+    /// que cada uma usa a mais e a conversão para 8 bits dos encoders.
+    fn working_bytes(&self, header: &Header) -> u64 {
+        let (mut width, mut height) = (header.width, header.height);
+        let mut largest = pixels(width, height);
+        let mut scratch = 0;
+        for op in &self.pipeline {
+            (width, height) = op.output_size(width, height);
+            largest = largest.max(pixels(width, height));
+            scratch = scratch.max(op.scratch_bytes_per_pixel());
+        }
+        if self.max_bytes.is_some() && self.resize_opts.linear_light {
+            // --max-bytes pode reduzir a imagem, também em luz linear
+            scratch = scratch.max(32);
+        }
+
+        let copies = if self.pipeline.is_empty() { 0 } else { 2 };
+        largest * (copies * header.bytes_per_pixel() + scratch + 8)
+    }
+}
+
+/// Configuração resolvida de uma execução.
+#[derive(Debug)]
+pub struct Job {
+    pub variants: Vec<Variant>,
+}
+
+/// Arquivo de entrada e seu caminho relativo à entrada de onde veio.
+#[derive(Debug, Clone)]
+pub struct InputFile {
+    /// Caminho no disco ou, para entradas de pacotes, caminho virtual
+    /// (`bundle.zip!/images/a.png`)
+    pub path: PathBuf,
+    pub relative: PathBuf,
+    /// Entrada dentro de um pacote (.zip, .tar, .tar.gz)
+    pub entry: Option<Entry>,
+}
+
+impl InputFile {
+    /// Arquivo no disco: o próprio arquivo ou o pacote de onde ele vem.
+    pub fn disk_path(&self) -> &Path {
+        self.entry.as_ref().map_or(&self.path, |e| e.archive())
+    }
+}
+
+/// Imagem de entrada já decodificada, reaproveitada por todas as variantes.
+pub struct Source {
+    pub file: InputFile,
+    pub format: ImageFormat,
+    pub size: u64,
+    pub image: DynamicImage,
+}
+
+/// Formato e dimensões de uma imagem, lidos só do cabeçalho.
+pub struct Header {
+    pub file: InputFile,
+    pub format: ImageFormat,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Header {
+    /// Memória estimada da imagem decodificada (RGBA de 8 bits).
+    pub fn estimated_bytes(&self) -> u64 {
+        pixels(self.width, self.height) * 4
+    }
+
+    /// Bytes por pixel da imagem decodificada no pior caso do formato: PNG,
+    /// TIFF e PNM podem ter 16 bits por canal e EXR/HDR vêm em ponto flutuante.
+    fn bytes_per_pixel(&self) -> u64 {
+        match self.format {
+            ImageFormat::Png | ImageFormat::Tiff | ImageFormat::Pnm => 8,
+            ImageFormat::OpenExr | ImageFormat::Hdr => 16,
+            _ => 4,
+        }
+    }
+
+    /// Memória a reservar no orçamento para gerar as variantes, uma de cada
+    /// vez: a imagem decodificada mais as cópias de trabalho da mais pesada.
+    /// Entradas de pacotes somam o conteúdo lido para a memória.
+    pub fn peak_bytes<'a>(&self, variants: impl IntoIterator<Item = &'a Variant>) -> u64 {
+        let working = variants
+            .into_iter()
+            .map(|variant| variant.working_bytes(self))
+            .max()
+            .unwrap_or(0);
+        let data = if self.file.entry.is_some() {
+            self.size
+        } else {
+            0
+        };
+        pixels(self.width, self.height) * self.bytes_per_pixel() + working + data
+    }
+}
+
+fn pixels(width: u32, height: u32) -> u64 {
+    u64::from(width) * u64::from(height)
+}
+
+const MIB: f64 = (1 << 20) as f64;
+
+/// Limites aplicados antes e durante a decodificação de cada imagem.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Largura ou altura máxima, em pixels
+    pub max_dimension: Option<u32>,
+    /// Memória máxima que o decoder pode alocar para uma imagem
+    pub max_alloc: u64,
+}
+
+impl Limits {
+    /// Recusa a imagem antes de decodificar se ela passar dos limites.
+    pub fn check(&self, header: &Header) -> Result<()> {
+        if let Some(max) = self.max_dimension
+            && (header.width > max || header.height > max)
+        {
+            bail!(
+                "imagem de {}x{} excede o limite de {max} px por lado (--max-dimension)",
+                header.width,
+                header.height
+            );
+        }
+        let needed = header.estimated_bytes();
+        if needed > self.max_alloc {
+            bail!(
+                "imagem de {}x{} precisaria de ~{:.1} MiB para decodificar, acima do limite de {:.1} MiB (--max-alloc)",
+                header.width,
+                header.height,
+                needed as f64 / MIB,
+                self.max_alloc as f64 / MIB
+            );
+        }
+        Ok(())
+    }
+
+    fn decoder_limits(&self) -> image::io::Limits {
+        let mut limits = image::io::Limits::default();
+        limits.max_image_width = self.max_dimension;
+        limits.max_image_height = self.max_dimension;
+        limits.max_alloc = Some(self.max_alloc);
+        limits
+    }
+}
+
+/// Abre o arquivo identificando o formato pelo conteúdo, não pela extensão.
+fn open(path: &Path) -> Result<image::io::Reader<BufReader<File>>> {
+    Ok(image::io::Reader::new(BufReader::new(File::open(path)?)).with_guessed_format()?)
+}
+
+/// Quanto do começo de uma entrada de pacote é lido para achar o cabeçalho.
+const HEADER_PREFIX: u64 = 1 << 20;
+
+/// Lê o cabeçalho de um arquivo. Devolve `None` se não for uma imagem.
+pub fn read_header(file: &InputFile, limits: &Limits) -> Result<Option<Header>> {
+    if let Some(entry) = &file.entry {
+        return read_entry_header(file, entry, limits);
+    }
+    let reader = open(&file.path)?;
+
+    // Se não for imagem, ignora
+    let Some(format) = reader.format() else {
+        return Ok(None);
+    };
+    let (width, height) = reader.into_dimensions()?;
+
+    Ok(Some(Header {
+        file: file.clone(),
+        format,
+        size: fs::metadata(&file.path)?.len(),
+        width,
+        height,
+    }))
+}
+
+/// Cabeçalho de uma entrada de pacote, lido só do começo dela; se o começo
+/// não bastar (ex: TIFF com o diretório no fim), a entrada é lida inteira.
+fn read_entry_header(file: &InputFile, entry: &Entry, limits: &Limits) -> Result<Option<Header>> {
+    let prefix = entry.read_prefix(HEADER_PREFIX, limits.max_alloc)?;
+    let header = match read_header_from(file, &prefix) {
+        Err(_) if entry.size() > prefix.len() as u64 => {
+            read_header_from(file, &entry.read(limits.max_alloc)?)?
+        }
+        header => header?,
+    };
+    Ok(header.map(|header| Header {
+        size: entry.size(),
+        ..header
+    }))
+}
+
+/// Decodifica a imagem direto do arquivo, sem copiá-lo inteiro para a memória.
+///
+/// Entradas de pacotes são decodificadas de `data`, se já foram lidas, ou
+/// lidas para a memória agora.
+pub fn decode(header: Header, data: Option<&[u8]>, limits: &Limits) -> Result<Source> {
+    if let Some(entry) = header.file.entry.clone() {
+        let data = match data {
+            Some(data) => Cow::Borrowed(data),
+            None => Cow::Owned(entry.read(limits.max_alloc)?),
+        };
+        return decode_from(header, &data, limits);
+    }
+    let mut reader = open(&header.file.path)?;
+    reader.limits(limits.decoder_limits());
+    let image = reader.decode()?;
+
+    Ok(Source {
+        file: header.file,
+        format: header.format,
+        size: header.size,
+        image,
+    })
+}
+
+/// Como [`read_header`], para uma imagem já em memória (ex: lida da entrada
+/// padrão); o formato é detectado pelo conteúdo.
+pub fn read_header_from(file: &InputFile, data: &[u8]) -> Result<Option<Header>> {
+    let reader = image::io::Reader::new(Cursor::new(data)).with_guessed_format()?;
+
+    let Some(format) = reader.format() else {
+        return Ok(None);
+    };
+    let (width, height) = reader.into_dimensions()?;
+
+    Ok(Some(Header {
+        file: file.clone(),
+        format,
+        size: data.len() as u64,
+        width,
+        height,
+    }))
+}
+
+/// Como [`decode`], para uma imagem já em memória.
+pub fn decode_from(header: Header, data: &[u8], limits: &Limits) -> Result<Source> {
+    let mut reader = image::io::Reader::with_format(Cursor::new(data), header.format);
+    reader.limits(limits.decoder_limits());
+    let image = reader.decode()?;
+
+    Ok(Source {
+        file: header.file,
+        format: header.format,
+        size: header.size,
+        image,
+    })
+}
+
+/// Orçamento de memória compartilhado entre as imagens em processamento.
+///
+/// Cada imagem reserva sua memória estimada antes de decodificar e espera
+/// enquanto a soma das reservas passaria do limite. Uma imagem maior que o
+/// orçamento inteiro ainda é processada, mas sozinha.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: Option<u64>,
+    in_use: Mutex<u64>,
+    released: Condvar,
+}
+
+/// Reserva de memória, devolvida ao orçamento quando sai de escopo.
+pub struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: Option<u64>) -> Self {
+        MemoryBudget {
+            limit,
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Bloqueia até haver espaço para `bytes` no orçamento.
+    pub fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let Some(limit) = self.limit else {
+            return Reservation {
+                budget: self,
+                bytes: 0,
+            };
+        };
+
+        let mut in_use = self.in_use.lock().unwrap();
+        while *in_use > 0 && *in_use + bytes > limit {
+            in_use = self.released.wait(in_use).unwrap();
+        }
+        *in_use += bytes;
+
+        Reservation {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if self.bytes > 0 {
+            *self.budget.in_use.lock().unwrap() -= self.bytes;
+            self.budget.released.notify_all();
+        }
+    }
+}
+
+/// Como os arquivos de saída são organizados no diretório de saída.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    /// Grava tudo na raiz da saída em vez de repetir as subpastas da entrada
+    pub flatten: bool,
+    /// Modelo de nome (`--name`); sem ele, `{stem}[-{variant}].{ext}`
+    pub name: Option<NameTemplate>,
+}
+
+impl Layout {
+    /// Caminho de saída de uma variante já codificada.
+    ///
+    /// `counter` é a posição da saída na execução, começando em 1.
+    pub fn path(&self, rendition: &Rendition, counter: usize) -> PathBuf {
+        let relative = &rendition.file.relative;
+        let stem = relative
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        let dir = match relative.parent() {
+            Some(dir) if !self.flatten => dir.to_string_lossy().replace('\\', "/"),
+            _ => String::new(),
+        };
+        let report = &rendition.report;
+
+        let mut out = rendition.output.clone();
+        match &self.name {
+            Some(template) => {
+                if !template.uses_dir() {
+                    out.push(&dir);
+                }
+                out.push(template.render(&NameFields {
+                    stem,
+                    dir: &dir,
+                    width: report.width,
+                    height: report.height,
+                    format: report.format,
+                    preset: rendition.preset.as_deref(),
+                    variant: report.variant.as_deref(),
+                    data: rendition.data.as_deref(),
+                    counter,
+                }));
+            }
+            None => {
+                out.push(&dir);
+                let ext = report.format.extension();
+                match &report.variant {
+                    Some(name) => out.push(format!("{stem}-{name}.{ext}")),
+                    None => out.push(format!("{stem}.{ext}")),
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Variante codificada, pronta para ser gravada.
+pub struct Rendition {
+    pub file: InputFile,
+    /// Diretório de saída da variante
+    pub output: PathBuf,
+    pub preset: Option<String>,
+    /// Conteúdo codificado; `None` quando a variante só foi planejada
+    /// (--dry-run) ou veio do manifesto incremental
+    pub data: Option<Vec<u8>>,
+    pub report: ImageReport,
+    /// Chave no manifesto incremental (--incremental)
+    pub cache: Option<CacheKey>,
+}
+
+impl Rendition {
+    /// Variante que não precisou ser regerada, com o relatório da execução
+    /// em que foi gravada.
+    pub fn unchanged(file: &InputFile, variant: &Variant, report: ImageReport) -> Self {
+        Rendition {
+            file: file.clone(),
+            output: variant.output.clone(),
+            preset: variant.preset.clone(),
+            data: None,
+            report,
+            cache: None,
+        }
+    }
+}
+
+/// Gera uma variante de uma imagem: aplica o pipeline de operações,
+/// conversão de formato e gera um registro para o relatório.
+///
+/// Nada é gravado aqui; quem chama decide o caminho final e grava os dados.
+pub fn process_image(source: &Source, variant: &Variant) -> Result<Rendition> {
+    // Sem operações, a imagem decodificada é usada direto, sem cópia
+    let mut img = Cow::Borrowed(&source.image);
+
+    // Aplica as operações na ordem pedida
+    for op in &variant.pipeline {
+        img = Cow::Owned(op.apply(img.into_owned())?);
+    }
+
+    // Define formato de saída
+    let new_format = variant
+        .format
+        .unwrap_or_else(|| OutputFormat::default_for(source.format));
+
+    let (data, img, opts) = match variant.max_bytes {
+        Some(max_bytes) => {
+            let fitted = target_size::encode_within(
+                &img,
+                new_format,
+                &variant.encode,
+                &variant.resize_opts,
+                max_bytes,
+            )?;
+            (fitted.data, Cow::Owned(fitted.image), fitted.opts)
+        }
+        None => (
+            format::encode(&img, new_format, &variant.encode)?,
+            img,
+            variant.encode.clone(),
+        ),
+    };
+
+    Ok(rendition(
+        &source.file,
+        (source.format, source.size),
+        variant,
+        new_format,
+        (img.width(), img.height()),
+        &opts,
+        Some(data),
+    ))
+}
+
+/// Planeja uma variante só a partir do cabeçalho, sem decodificar nem
+/// codificar: as dimensões vêm do pipeline e o tamanho final fica em zero.
+///
+/// Com --max-bytes a imagem ainda pode ser reduzida na hora de codificar.
+pub fn plan_image(header: &Header, variant: &Variant) -> Rendition {
+    let (mut width, mut height) = (header.width, header.height);
+    for op in &variant.pipeline {
+        (width, height) = op.output_size(width, height);
+    }
+
+    let new_format = variant
+        .format
+        .unwrap_or_else(|| OutputFormat::default_for(header.format));
+
+    rendition(
+        &header.file,
+        (header.format, header.size),
+        variant,
+        new_format,
+        (width, height),
+        &variant.encode,
+        None,
+    )
+}
+
+fn rendition(
+    file: &InputFile,
+    (original_format, original_size): (ImageFormat, u64),
+    variant: &Variant,
+    new_format: OutputFormat,
+    (width, height): (u32, u32),
+    opts: &EncodeOptions,
+    data: Option<Vec<u8>>,
+) -> Rendition {
+    let report = ImageReport {
+        input: file.path.display().to_string(),
+        output: String::new(),
+        variant: variant.name_for(width),
+        original_format: format!("{original_format:?}"),
+        new_format: new_format.name().to_string(),
+        original_size,
+        new_size: data.as_ref().map_or(0, |d| d.len() as u64),
+        width,
+        height,
+        quality: opts.quality(new_format),
+        png: (new_format == OutputFormat::Png).then(|| opts.png_settings()),
+        jpeg: (new_format == OutputFormat::Jpeg).then(|| opts.jpeg_settings()),
+        action: Action::Created,
+        reason: None,
+        format: new_format,
+    };
+
+    Rendition {
+        file: file.clone(),
+        output: variant.output.clone(),
+        preset: variant.preset.clone(),
+        data,
+        report,
+        cache: None,
+    }
+}
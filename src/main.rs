@@ -1,219 +1,205 @@
+mod archive;
+mod batch;
+mod cache;
+mod config;
+mod format;
+mod job;
+mod naming;
+mod ops;
+mod resize;
+mod responsive;
+mod stream;
+mod target_size;
+mod walk;
+mod watch;
+
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
 
-use anyhow::Result;
-use clap::Parser;
-use image::{DynamicImage, ImageFormat};
-use serde::Serialize;
-use walkdir::WalkDir;
+use crate::batch::{BatchOptions, process_all};
+use crate::format::{EncodeOptions, OutputFormat};
+use crate::job::{ImageReport, Job, Variant};
+use crate::ops::Operation;
+use crate::resize::{Geometry, ResizeOptions};
+use crate::walk::{Inputs, WalkOptions};
 
 #[derive(Parser, Debug)]
 #[command(
     name = "img-tool",
     version,
-    about = "Ferramenta de linha de comando para processar imagens (conversão, resize, grayscale e relatório)"
+    about = "Ferramenta de linha de comando para processar imagens (conversão, resize, grayscale e relatório)",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 struct Cli {
-    /// Arquivo ou diretório de entrada
-    input: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Diretório de saída
-    #[arg(long, default_value = "output")]
-    output: PathBuf,
+    #[command(flatten)]
+    inputs: Inputs,
 
-    /// Formato de saída (jpg ou png por enquanto)
-    #[arg(long)]
-    to_format: Option<String>,
+    #[command(flatten)]
+    process: ProcessArgs,
 
-    /// Redimensionar para LARGURAxALTURA (ex: 800x600)
-    #[arg(long)]
-    resize: Option<String>,
+    #[command(flatten)]
+    batch: BatchOptions,
 
-    /// Converter para tons de cinza
+    #[command(flatten)]
+    walk: WalkOptions,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Executa um pipeline descrito em arquivo (TOML, JSON ou YAML)
+    Run(RunArgs),
+    /// Gera uma escada de larguras e o HTML com srcset/sizes/<picture>
+    Responsive(responsive::ResponsiveArgs),
+    /// Observa um diretório e processa as imagens conforme são criadas ou
+    /// modificadas, apagando as saídas das que forem removidas
+    Watch(watch::WatchArgs),
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Arquivo de configuração do pipeline
     #[arg(long)]
-    grayscale: bool,
+    config: PathBuf,
 
-    /// Caminho para salvar relatório em JSON
+    /// Preset do arquivo a usar (padrão: as configurações de nível superior).
+    /// Pode repetir para gerar vários presets decodificando cada imagem uma vez
     #[arg(long)]
-    report: Option<PathBuf>,
+    preset: Vec<String>,
 }
 
-#[derive(Serialize, Debug)]
-struct ImageReport {
-    input: String,
-    output: String,
-    original_format: String,
-    new_format: String,
-    original_size: u64,
-    new_size: u64,
-}
+/// Opções de processamento passadas diretamente na linha de comando.
+#[derive(Args, Debug)]
+struct ProcessArgs {
+    /// Diretório de saída
+    #[arg(long, default_value = "output")]
+    output: PathBuf,
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
+    /// Formato de saída (aceita também extensões como jpg, tif, pnm, openexr).
+    /// Vários formatos separados por vírgula geram uma saída para cada
+    #[arg(long, value_enum, value_delimiter = ',')]
+    to_format: Vec<OutputFormat>,
 
-    // Garante que o diretório de saída existe
-    fs::create_dir_all(&args.output)?;
+    /// Larguras das variantes a gerar de cada imagem (ex: 320,640,1280); nunca
+    /// amplia: uma imagem mais estreita sai com a própria largura no nome
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u32).range(1..))]
+    widths: Vec<u32>,
 
-    let mut reports = Vec::new();
+    #[command(flatten)]
+    encode: EncodeOptions,
 
-    let paths = collect_paths(&args.input)?;
+    /// Redimensionar com geometria: 800x600, 800x, x600, 50%, 800x600> (só reduz),
+    /// 800x600< (só amplia), 400x300@2x (densidade)
+    #[arg(long)]
+    resize: Option<Geometry>,
 
-    if paths.is_empty() {
-        eprintln!("Nenhum arquivo encontrado para processar.");
-        return Ok(());
-    }
+    #[command(flatten)]
+    resize_opts: ResizeOptions,
 
-    println!("Encontrados {} arquivo(s) para processar.", paths.len());
+    /// Tamanho máximo do arquivo de saída (ex: 200KB, 1.5MB, 512KiB); reduz a
+    /// qualidade e, se preciso, as dimensões até caber
+    #[arg(long, value_parser = target_size::parse_byte_size)]
+    max_bytes: Option<u64>,
 
-    for path in paths {
-        match process_image(&path, &args) {
-            Ok(Some(report)) => {
-                println!("OK  -> {}", report.output);
-                reports.push(report);
-            }
-            Ok(None) => {
-                println!("IGN -> {}", path.display());
-            }
-            Err(e) => {
-                eprintln!("ERR -> {}: {e}", path.display());
-            }
-        }
-    }
+    /// Converter para tons de cinza
+    #[arg(long)]
+    grayscale: bool,
 
-    // Se foi pedido relatório, salva em JSON
-    if let Some(report_path) = args.report {
-        let json = serde_json::to_string_pretty(&reports)?;
-        fs::write(&report_path, json)?;
-        println!("Relatório salvo em: {}", report_path.display());
-    }
+    /// Operação do pipeline, aplicada na ordem em que aparece (pode repetir).
+    /// Ex: --op crop=800x600+10+10 --op resize=400x,filter=nearest --op sharpen=1.5.
+    /// Disponíveis: resize, crop, grayscale, sharpen, blur, rotate, flip.
+    /// --resize e --grayscale, se usados, rodam antes das operações --op
+    #[arg(long = "op", value_name = "OPERAÇÃO")]
+    ops: Vec<String>,
 
-    Ok(())
+    /// Caminho para salvar relatório em JSON
+    #[arg(long)]
+    report: Option<PathBuf>,
 }
 
-/// Coleta todos os caminhos de arquivos a partir de um arquivo único ou diretório.
-fn collect_paths(input: &Path) -> Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
-
-    if input.is_file() {
-        files.push(input.to_path_buf());
-    } else if input.is_dir() {
-        for entry in WalkDir::new(input) {
-            let entry = entry?;
-            if entry.file_type().is_file() {
-                files.push(entry.path().to_path_buf());
-            }
-        }
-    } else {
-        eprintln!("Entrada não é arquivo nem diretório: {}", input.display());
-    }
-
-    Ok(files)
-}
+impl Job {
+    /// Monta o job a partir das opções da linha de comando.
+    fn from_args(args: &ProcessArgs) -> Result<Job> {
+        let mut pipeline: Vec<Arc<dyn Operation>> = Vec::new();
 
-/// Processa uma única imagem: aplica resize, grayscale, conversão de formato
-/// e gera um registro para o relatório.
-fn process_image(path: &Path, args: &Cli) -> Result<Option<ImageReport>> {
-    // Lê metadados
-    let metadata = fs::metadata(path)?;
-    let original_size = metadata.len();
-
-    // Lê binário do arquivo
-    let data = fs::read(path)?;
-
-    // Tenta identificar o formato
-    let format = match image::guess_format(&data) {
-        Ok(f) => f,
-        Err(_) => {
-            // Se não for imagem, ignora
-            return Ok(None);
+        if let Some(geometry) = args.resize {
+            pipeline.push(Arc::new(ops::Resize::new(
+                geometry,
+                args.resize_opts.clone(),
+            )));
         }
-    };
-
-    let original_format = format!("{:?}", format);
-
-    // Carrega imagem
-    let mut img = image::load_from_memory(&data)?;
-
-    // Aplica resize se solicitado
-    if let Some(resize_str) = &args.resize {
-        if let Some((w, h)) = parse_resize(resize_str) {
-            img = img.resize_exact(w, h, image::imageops::FilterType::Lanczos3);
-        } else {
-            eprintln!("Parâmetro inválido em --resize (use LARGURAxALTURA), ignorando resize.");
+        if args.grayscale {
+            pipeline.push(Arc::new(ops::Grayscale));
+        }
+        for spec in &args.ops {
+            pipeline.push(ops::parse_op(spec, &args.resize_opts)?);
         }
-    }
 
-    // Aplica grayscale se solicitado
-    if args.grayscale {
-        img = DynamicImage::ImageLuma8(img.to_luma8());
+        let variant = Variant {
+            name: None,
+            preset: None,
+            output: args.output.clone(),
+            format: None,
+            encode: args.encode.clone(),
+            resize_opts: args.resize_opts.clone(),
+            max_bytes: args.max_bytes,
+            pipeline,
+            width: None,
+            previous_width: None,
+        };
+
+        Ok(Job {
+            variants: variant.expand(&args.widths, &args.to_format),
+        })
     }
+}
 
-    // Define formato de saída
-    let new_format = args
-        .to_format
-        .as_deref()
-        .unwrap_or_else(|| default_output_format(format));
-
-    let output_path = build_output_path(path, &args.output, new_format);
-
-    // Cria arquivo de saída e escreve a imagem nele (File implementa Write + Seek)
-    let mut output_file = fs::File::create(&output_path)?;
-
-    match new_format {
-        "jpg" | "jpeg" => {
-            img.write_to(&mut output_file, ImageFormat::Jpeg)?;
-        }
-        "png" => {
-            img.write_to(&mut output_file, ImageFormat::Png)?;
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let batch = &cli.batch;
+    let walk = &cli.walk;
+
+    match cli.command {
+        Some(Command::Run(run)) => {
+            let file = config::load(&run.config)?;
+            let (paths, report, job) = file.job(&run.preset, walk)?;
+            let reports = process_all(paths, &job, batch)?;
+            write_report(&reports, report.as_deref())
         }
-        other => {
-            eprintln!(
-                "Formato de saída não suportado ({other}), usando PNG como fallback."
-            );
-            img.write_to(&mut output_file, ImageFormat::Png)?;
+        Some(Command::Responsive(args)) => responsive::run(&args, batch, walk),
+        Some(Command::Watch(args)) => watch::run(&args, batch, walk),
+        None => {
+            let args = cli.process;
+            let job = Job::from_args(&args)?;
+            if cli.inputs.is_stdin() {
+                return stream::run(&job, batch, args.report.as_deref());
+            }
+            let paths = cli.inputs.collect(walk)?;
+            let reports = process_all(paths, &job, batch)?;
+            write_report(&reports, args.report.as_deref())
         }
     }
-
-    let new_size = fs::metadata(&output_path)?.len();
-
-    Ok(Some(ImageReport {
-        input: path.display().to_string(),
-        output: output_path.display().to_string(),
-        original_format,
-        new_format: new_format.to_string(),
-        original_size,
-        new_size,
-    }))
 }
 
-/// Interpreta uma string do tipo "800x600" como (800, 600)
-fn parse_resize(s: &str) -> Option<(u32, u32)> {
-    let parts: Vec<&str> = s.split('x').collect();
-    if parts.len() != 2 {
-        return None;
+/// Se foi pedido relatório, salva em JSON.
+fn write_report(reports: &[ImageReport], report: Option<&Path>) -> Result<()> {
+    if let Some(report_path) = report {
+        let json = serde_json::to_string_pretty(reports)?;
+        fs::write(report_path, json).with_context(|| {
+            format!(
+                "não foi possível gravar o relatório {}",
+                report_path.display()
+            )
+        })?;
+        println!("Relatório salvo em: {}", report_path.display());
     }
-    let w = parts[0].parse().ok()?;
-    let h = parts[1].parse().ok()?;
-    Some((w, h))
-}
 
-/// Define um formato padrão de saída se o usuário não especificar.
-fn default_output_format(input_format: ImageFormat) -> &'static str {
-    match input_format {
-        ImageFormat::Png => "jpg",
-        ImageFormat::Jpeg => "png",
-        _ => "png",
-    }
-}
-
-/// Monta o caminho de saída baseado no diretório de saída e na nova extensão.
-fn build_output_path(input: &Path, output_dir: &Path, new_ext: &str) -> PathBuf {
-    let file_stem = input
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("output");
-
-    let mut out = output_dir.to_path_buf();
-    out.push(format!("{file_stem}.{new_ext}"));
-    out
+    Ok(())
 }
@@ -0,0 +1,281 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::format::OutputFormat;
+
+/// Modelo de nome de arquivo de `--name`, como `{stem}-{width}x{height}.{ext}`.
+///
+/// O resultado é um caminho relativo ao diretório de saída e pode conter `/`.
+/// Use `{{` e `}}` para chaves literais.
+#[derive(Debug, Clone)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Stem,
+    Dir,
+    Width,
+    Height,
+    Format,
+    Ext,
+    Preset,
+    Variant,
+    /// Hash do conteúdo gerado, com a quantidade de dígitos hexadecimais
+    Hash(usize),
+    /// Contador da execução, com a largura mínima (completada com zeros)
+    Counter(usize),
+}
+
+/// Placeholders aceitos, para mensagens de erro.
+const PLACEHOLDERS: &str =
+    "stem, dir, width, height, format, ext, preset, variant, hash[:N], counter[:N]";
+
+/// Valores disponíveis para montar o nome de uma saída.
+pub struct NameFields<'a> {
+    pub stem: &'a str,
+    pub dir: &'a str,
+    pub width: u32,
+    pub height: u32,
+    pub format: OutputFormat,
+    pub preset: Option<&'a str>,
+    pub variant: Option<&'a str>,
+    /// Conteúdo gerado; sem ele (--dry-run) o hash fica como `{hash}`
+    pub data: Option<&'a [u8]>,
+    pub counter: usize,
+}
+
+impl NameTemplate {
+    /// Diz se o modelo usa `{dir}`; sem ele, as subpastas da entrada são
+    /// acrescentadas antes do nome.
+    pub fn uses_dir(&self) -> bool {
+        self.parts.iter().any(|p| matches!(p, Part::Dir))
+    }
+
+    pub fn render(&self, fields: &NameFields) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Stem => out.push_str(fields.stem),
+                Part::Dir => out.push_str(fields.dir),
+                Part::Width => write!(out, "{}", fields.width).unwrap(),
+                Part::Height => write!(out, "{}", fields.height).unwrap(),
+                Part::Format => out.push_str(fields.format.name()),
+                Part::Ext => out.push_str(fields.format.extension()),
+                Part::Preset => out.push_str(fields.preset.unwrap_or_default()),
+                Part::Variant => out.push_str(fields.variant.unwrap_or_default()),
+                Part::Hash(len) => match fields.data {
+                    Some(data) => out.push_str(&blake3::hash(data).to_hex()[..*len]),
+                    None => out.push_str("{hash}"),
+                },
+                Part::Counter(width) => write!(out, "{:0width$}", fields.counter).unwrap(),
+            }
+        }
+
+        // `{dir}` vazio não deve deixar barras sobrando
+        let segments: Vec<&str> = out.split('/').filter(|s| !s.is_empty()).collect();
+        segments.join("/")
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        placeholder.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("'{{{placeholder}' sem '}}' de fechamento"));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::parse(&placeholder)?);
+                }
+                '}' => return Err("'}' sem '{' correspondente (use '}}')".to_string()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        if !parts.iter().any(|p| !matches!(p, Part::Literal(_))) {
+            return Err(format!(
+                "o modelo precisa de pelo menos um placeholder ({PLACEHOLDERS})"
+            ));
+        }
+        Ok(NameTemplate { parts })
+    }
+}
+
+impl Part {
+    fn parse(placeholder: &str) -> Result<Self, String> {
+        let (name, arg) = match placeholder.split_once(':') {
+            Some((name, arg)) => {
+                let arg: usize = arg
+                    .parse()
+                    .map_err(|_| format!("tamanho inválido em {{{placeholder}}}"))?;
+                (name, Some(arg))
+            }
+            None => (placeholder, None),
+        };
+
+        let part = match (name, arg) {
+            ("stem", None) => Part::Stem,
+            ("dir", None) => Part::Dir,
+            ("width", None) => Part::Width,
+            ("height", None) => Part::Height,
+            ("format", None) => Part::Format,
+            ("ext", None) => Part::Ext,
+            ("preset", None) => Part::Preset,
+            ("variant", None) => Part::Variant,
+            ("hash", None) => Part::Hash(8),
+            ("hash", Some(len @ 1..=64)) => Part::Hash(len),
+            ("hash", Some(_)) => return Err("{hash:N} aceita de 1 a 64 dígitos".to_string()),
+            ("counter", width) => Part::Counter(width.unwrap_or(0)),
+            _ => {
+                return Err(format!(
+                    "placeholder desconhecido: {{{placeholder}}} (disponíveis: {PLACEHOLDERS})"
+                ));
+            }
+        };
+        Ok(part)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, fields: &NameFields) -> String {
+        template.parse::<NameTemplate>().unwrap().render(fields)
+    }
+
+    fn fields() -> NameFields<'static> {
+        NameFields {
+            stem: "foto",
+            dir: "viagem/praia",
+            width: 640,
+            height: 480,
+            format: OutputFormat::Jpeg,
+            preset: Some("thumb"),
+            variant: Some("thumb-640w"),
+            data: Some(b"conteudo"),
+            counter: 7,
+        }
+    }
+
+    #[test]
+    fn placeholders_sao_substituidos() {
+        let fields = fields();
+        assert_eq!(
+            render("{stem}-{width}x{height}.{ext}", &fields),
+            "foto-640x480.jpg"
+        );
+        assert_eq!(
+            render("{dir}/{stem}.{format}", &fields),
+            "viagem/praia/foto.jpeg"
+        );
+        assert_eq!(
+            render("{preset}/{variant}.{ext}", &fields),
+            "thumb/thumb-640w.jpg"
+        );
+    }
+
+    #[test]
+    fn chaves_duplicadas_viram_literais() {
+        assert_eq!(render("{{{stem}}}.{ext}", &fields()), "{foto}.jpg");
+    }
+
+    #[test]
+    fn hash_tem_8_digitos_ou_o_tamanho_pedido() {
+        let fields = fields();
+        let hash = blake3::hash(b"conteudo").to_hex();
+        assert_eq!(render("{hash}", &fields), &hash[..8]);
+        assert_eq!(render("{hash:12}", &fields), &hash[..12]);
+        assert_eq!(render("{hash:64}", &fields), hash.as_str());
+
+        let planned = NameFields {
+            data: None,
+            ..fields
+        };
+        assert_eq!(render("{stem}.{hash}", &planned), "foto.{hash}");
+    }
+
+    #[test]
+    fn contador_completa_com_zeros() {
+        let fields = fields();
+        assert_eq!(render("{counter}", &fields), "7");
+        assert_eq!(render("{counter:4}", &fields), "0007");
+        let counter = 12345;
+        assert_eq!(
+            render("{counter:4}", &NameFields { counter, ..fields }),
+            "12345"
+        );
+    }
+
+    #[test]
+    fn dir_vazio_nao_deixa_barras() {
+        let fields = NameFields {
+            dir: "",
+            preset: None,
+            ..fields()
+        };
+        assert_eq!(render("{dir}/{stem}.{ext}", &fields), "foto.jpg");
+        assert_eq!(render("{preset}/{dir}/{stem}.{ext}", &fields), "foto.jpg");
+    }
+
+    #[test]
+    fn usa_dir() {
+        assert!("{dir}/{stem}".parse::<NameTemplate>().unwrap().uses_dir());
+        assert!(!"{stem}".parse::<NameTemplate>().unwrap().uses_dir());
+    }
+
+    #[test]
+    fn modelo_invalido() {
+        for s in [
+            "",
+            "foto.jpg",
+            "{{stem}}",
+            "{stem",
+            "stem}",
+            "{nome}",
+            "{hash:0}",
+            "{hash:65}",
+            "{hash:x}",
+            "{counter:-1}",
+            "{width:3}",
+        ] {
+            assert!(
+                s.parse::<NameTemplate>().is_err(),
+                "{s:?} deveria ser inválido"
+            );
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use image::DynamicImage;
+
+use crate::resize::{self, Filter, Geometry, ResizeMode, ResizeOptions};
+
+/// Uma etapa do pipeline de processamento.
+///
+/// Para criar uma nova transformação basta implementar este trait e
+/// registrar o construtor em [`OPERATIONS`].
+pub trait Operation: Debug + Send + Sync {
+    /// Aplica a transformação, devolvendo a nova imagem.
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage>;
+
+    /// Dimensões resultantes para uma imagem de `width`x`height`, usadas para
+    /// planejar a saída sem decodificar (--dry-run).
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        (width, height)
+    }
+
+    /// Memória temporária, em bytes por pixel, que a operação usa além da
+    /// imagem de entrada e da de saída (para o orçamento de --memory-budget).
+    fn scratch_bytes_per_pixel(&self) -> u64 {
+        0
+    }
+}
+
+type Constructor = fn(&Params, &ResizeOptions) -> Result<Box<dyn Operation>>;
+
+/// Operações disponíveis em `--op nome=parâmetros`.
+const OPERATIONS: &[(&str, Constructor)] = &[
+    ("resize", Resize::parse),
+    ("crop", Crop::parse),
+    ("grayscale", Grayscale::parse),
+    ("sharpen", Sharpen::parse),
+    ("blur", Blur::parse),
+    ("rotate", Rotate::parse),
+    ("flip", Flip::parse),
+];
+
+/// Interpreta uma operação no formato `nome=valor,chave=valor,...`.
+///
+/// `defaults` fornece os valores de --resize-mode, --filter etc. usados
+/// quando o resize não os especifica.
+pub fn parse_op(spec: &str, defaults: &ResizeOptions) -> Result<Arc<dyn Operation>> {
+    let (name, args) = spec.split_once('=').unwrap_or((spec, ""));
+    let Some((_, constructor)) = OPERATIONS.iter().find(|(n, _)| *n == name) else {
+        let known: Vec<_> = OPERATIONS.iter().map(|(n, _)| *n).collect();
+        bail!(
+            "operação desconhecida em --op: {name} (disponíveis: {})",
+            known.join(", ")
+        );
+    };
+
+    constructor(&Params::parse(args), defaults)
+        .map(Arc::from)
+        .map_err(|e| anyhow::anyhow!("--op {spec}: {e}"))
+}
+
+/// Parâmetros de uma operação: valores posicionais e pares chave=valor.
+struct Params<'a> {
+    positional: Vec<&'a str>,
+    named: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Params<'a> {
+    fn parse(args: &'a str) -> Self {
+        let mut positional = Vec::new();
+        let mut named = Vec::new();
+        for part in args.split(',').filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some((key, value)) => named.push((key, value)),
+                None => positional.push(part),
+            }
+        }
+        Params { positional, named }
+    }
+
+    fn positional(&self, index: usize) -> Option<&'a str> {
+        self.positional.get(index).copied()
+    }
+
+    fn named(&self, key: &str) -> Option<&'a str> {
+        self.named.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    fn flag(&self, key: &str) -> bool {
+        self.positional.contains(&key) || self.named(key).is_some_and(|v| v != "false")
+    }
+}
+
+/// Lê um número opcional, com mensagem de erro indicando o parâmetro.
+fn number<T: std::str::FromStr>(value: Option<&str>, what: &str) -> Result<Option<T>> {
+    value
+        .map(|v| {
+            v.parse()
+                .map_err(|_| anyhow::anyhow!("{what} inválido: {v}"))
+        })
+        .transpose()
+}
+
+/// `resize=GEOMETRIA[,mode=fit][,filter=nearest][,background=#fff][,linear]`
+#[derive(Debug)]
+pub struct Resize {
+    geometry: Geometry,
+    opts: ResizeOptions,
+}
+
+impl Resize {
+    pub fn new(geometry: Geometry, opts: ResizeOptions) -> Self {
+        Resize { geometry, opts }
+    }
+
+    fn parse(params: &Params, defaults: &ResizeOptions) -> Result<Box<dyn Operation>> {
+        let Some(geometry) = params.positional(0) else {
+            bail!("resize precisa de uma geometria (ex: resize=800x600)");
+        };
+        let geometry = geometry.parse().map_err(anyhow::Error::msg)?;
+
+        let mut opts = defaults.clone();
+        if let Some(mode) = params.named("mode") {
+            opts.mode = ResizeMode::from_str(mode, true).map_err(anyhow::Error::msg)?;
+        }
+        if let Some(filter) = params.named("filter") {
+            opts.filter = Filter::from_str(filter, true).map_err(anyhow::Error::msg)?;
+        }
+        if let Some(background) = params.named("background") {
+            opts.background = resize::parse_color(background).map_err(anyhow::Error::msg)?;
+        }
+        if params.flag("linear") {
+            opts.linear_light = true;
+        }
+
+        Ok(Box::new(Resize { geometry, opts }))
+    }
+}
+
+impl Operation for Resize {
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(match self.geometry.target(img.width(), img.height()) {
+            Some((w, h)) => resize::resize(&img, w, h, &self.opts),
+            None => img,
+        })
+    }
+
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.geometry.target(width, height) {
+            Some((w, h)) => resize::output_size(width, height, w, h, self.opts.mode),
+            None => (width, height),
+        }
+    }
+
+    fn scratch_bytes_per_pixel(&self) -> u64 {
+        // Em luz linear a entrada e a saída ganham cópias RGBA de 32 bits
+        if self.opts.linear_light { 32 } else { 0 }
+    }
+}
+
+/// `crop=LARGURAxALTURA[+X+Y]`; sem deslocamento, corta no centro.
+#[derive(Debug)]
+struct Crop {
+    width: u32,
+    height: u32,
+    offset: Option<(u32, u32)>,
+}
+
+impl Crop {
+    fn parse(params: &Params, _: &ResizeOptions) -> Result<Box<dyn Operation>> {
+        let spec = params.positional(0).unwrap_or_default();
+        let invalid = || anyhow::anyhow!("use crop=LARGURAxALTURA ou crop=LARGURAxALTURA+X+Y");
+
+        let mut parts = spec.split('+');
+        let (w, h) = parts
+            .next()
+            .and_then(|s| s.split_once('x'))
+            .ok_or_else(invalid)?;
+        let width: u32 = w.parse().ok().filter(|&v| v > 0).ok_or_else(invalid)?;
+        let height: u32 = h.parse().ok().filter(|&v| v > 0).ok_or_else(invalid)?;
+
+        let offset = match (parts.next(), parts.next(), parts.next()) {
+            (None, None, None) => None,
+            (Some(x), Some(y), None) => Some((
+                x.parse().map_err(|_| invalid())?,
+                y.parse().map_err(|_| invalid())?,
+            )),
+            _ => return Err(invalid()),
+        };
+
+        Ok(Box::new(Crop {
+            width,
+            height,
+            offset,
+        }))
+    }
+}
+
+impl Crop {
+    /// Canto do recorte: o deslocamento pedido ou o centro.
+    fn origin(&self, width: u32, height: u32) -> (u32, u32) {
+        self.offset.unwrap_or((
+            width.saturating_sub(self.width) / 2,
+            height.saturating_sub(self.height) / 2,
+        ))
+    }
+}
+
+impl Operation for Crop {
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        let (x, y) = self.origin(img.width(), img.height());
+        if x >= img.width() || y >= img.height() {
+            bail!(
+                "crop começa fora da imagem ({x},{y} em {}x{})",
+                img.width(),
+                img.height()
+            );
+        }
+        Ok(img.crop_imm(x, y, self.width, self.height))
+    }
+
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (x, y) = self.origin(width, height);
+        (
+            self.width.min(width.saturating_sub(x)),
+            self.height.min(height.saturating_sub(y)),
+        )
+    }
+}
+
+/// `grayscale`
+#[derive(Debug)]
+pub struct Grayscale;
+
+impl Grayscale {
+    fn parse(_: &Params, _: &ResizeOptions) -> Result<Box<dyn Operation>> {
+        Ok(Box::new(Grayscale))
+    }
+}
+
+impl Operation for Grayscale {
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(DynamicImage::ImageLuma8(img.to_luma8()))
+    }
+}
+
+/// `sharpen[=SIGMA[,LIMIAR]]` (unsharp mask)
+#[derive(Debug)]
+struct Sharpen {
+    sigma: f32,
+    threshold: i32,
+}
+
+impl Sharpen {
+    fn parse(params: &Params, _: &ResizeOptions) -> Result<Box<dyn Operation>> {
+        Ok(Box::new(Sharpen {
+            sigma: number(params.positional(0), "sigma")?.unwrap_or(1.0),
+            threshold: number(params.positional(1), "limiar")?.unwrap_or(0),
+        }))
+    }
+}
+
+impl Operation for Sharpen {
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(img.unsharpen(self.sigma, self.threshold))
+    }
+}
+
+/// `blur=SIGMA` (desfoque gaussiano)
+#[derive(Debug)]
+struct Blur {
+    sigma: f32,
+}
+
+impl Blur {
+    fn parse(params: &Params, _: &ResizeOptions) -> Result<Box<dyn Operation>> {
+        let Some(sigma) = number(params.positional(0), "sigma")? else {
+            bail!("blur precisa do sigma (ex: blur=2)");
+        };
+        Ok(Box::new(Blur { sigma }))
+    }
+}
+
+impl Operation for Blur {
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(img.blur(self.sigma))
+    }
+}
+
+/// `rotate=90|180|270` (sentido horário)
+#[derive(Debug)]
+struct Rotate {
+    degrees: u16,
+}
+
+impl Rotate {
+    fn parse(params: &Params, _: &ResizeOptions) -> Result<Box<dyn Operation>> {
+        match number(params.positional(0), "ângulo")? {
+            Some(degrees @ (90 | 180 | 270)) => Ok(Box::new(Rotate { degrees })),
+            _ => bail!("use rotate=90, rotate=180 ou rotate=270"),
+        }
+    }
+}
+
+impl Operation for Rotate {
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(match self.degrees {
+            90 => img.rotate90(),
+            180 => img.rotate180(),
+            _ => img.rotate270(),
+        })
+    }
+
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.degrees {
+            180 => (width, height),
+            _ => (height, width),
+        }
+    }
+}
+
+/// `flip=h|v`
+#[derive(Debug)]
+struct Flip {
+    horizontal: bool,
+}
+
+impl Flip {
+    fn parse(params: &Params, _: &ResizeOptions) -> Result<Box<dyn Operation>> {
+        match params.positional(0) {
+            Some("h") => Ok(Box::new(Flip { horizontal: true })),
+            Some("v") => Ok(Box::new(Flip { horizontal: false })),
+            _ => bail!("use flip=h ou flip=v"),
+        }
+    }
+}
+
+impl Operation for Flip {
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(if self.horizontal {
+            img.fliph()
+        } else {
+            img.flipv()
+        })
+    }
+}
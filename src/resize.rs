@@ -0,0 +1,481 @@
+use std::str::FromStr;
+
+use clap::{Args, Parser, ValueEnum};
+use image::imageops::{self, FilterType};
+use image::{ColorType, DynamicImage, Rgba, RgbaImage};
+use serde::Deserialize;
+
+use crate::config;
+
+/// Opções de redimensionamento vindas da linha de comando.
+#[derive(Args, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResizeOptions {
+    /// Como encaixar a imagem em --resize
+    #[arg(long = "resize-mode", value_enum, default_value_t = ResizeMode::Exact)]
+    #[serde(deserialize_with = "config::value_enum")]
+    pub mode: ResizeMode,
+
+    /// Cor de fundo usada por --resize-mode pad (#rrggbb, #rrggbbaa, white, black, transparent)
+    #[arg(long, default_value = "white", value_parser = parse_color)]
+    #[serde(deserialize_with = "config::color")]
+    pub background: Rgba<u8>,
+
+    /// Filtro de reamostragem (nearest preserva pixel art)
+    #[arg(long, value_enum, default_value_t = Filter::Lanczos3)]
+    #[serde(deserialize_with = "config::value_enum")]
+    pub filter: Filter,
+
+    /// Redimensiona em luz linear em vez de valores sRGB, evitando bordas
+    /// escurecidas ao reduzir imagens de alto contraste
+    #[arg(long)]
+    pub linear_light: bool,
+}
+
+impl Default for ResizeOptions {
+    /// Os mesmos valores padrão da linha de comando.
+    fn default() -> Self {
+        #[derive(Parser)]
+        struct Defaults {
+            #[command(flatten)]
+            opts: ResizeOptions,
+        }
+        Defaults::parse_from(["img-tool"]).opts
+    }
+}
+
+/// Como encaixar a imagem nas dimensões pedidas em --resize.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Cabe inteira dentro de LARGURAxALTURA, mantendo a proporção
+    Fit,
+    /// Preenche LARGURAxALTURA mantendo a proporção e corta o excesso
+    Cover,
+    /// Cabe inteira e completa o resto com a cor de fundo (letterbox)
+    Pad,
+    /// Usa exatamente LARGURAxALTURA, distorcendo se a proporção for diferente
+    Exact,
+}
+
+/// Filtros de reamostragem disponíveis.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl Filter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Gaussian => FilterType::Gaussian,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Redimensiona a imagem para (width, height) segundo o modo escolhido.
+pub fn resize(img: &DynamicImage, width: u32, height: u32, opts: &ResizeOptions) -> DynamicImage {
+    let filter = opts.filter.filter_type();
+
+    match opts.mode {
+        ResizeMode::Fit => in_light(img, opts, |i| i.resize(width, height, filter)),
+        ResizeMode::Cover => in_light(img, opts, |i| i.resize_to_fill(width, height, filter)),
+        ResizeMode::Exact => resample(img, width, height, opts),
+        ResizeMode::Pad => {
+            let fitted = in_light(img, opts, |i| i.resize(width, height, filter));
+            let mut canvas = RgbaImage::from_pixel(width, height, opts.background);
+            let x = (width - fitted.width()) / 2;
+            let y = (height - fitted.height()) / 2;
+            imageops::overlay(&mut canvas, &fitted.to_rgba8(), x.into(), y.into());
+
+            // Sem transparência na imagem nem no fundo, não há por que manter alfa
+            if opts.background[3] == u8::MAX && !img.color().has_alpha() {
+                DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8())
+            } else {
+                DynamicImage::ImageRgba8(canvas)
+            }
+        }
+    }
+}
+
+/// Dimensões que [`resize`] produz para uma imagem de `width`x`height`.
+pub fn output_size(
+    width: u32,
+    height: u32,
+    target_w: u32,
+    target_h: u32,
+    mode: ResizeMode,
+) -> (u32, u32) {
+    match mode {
+        ResizeMode::Fit => {
+            // Mesmo arredondamento de `DynamicImage::resize`
+            let ratio = f64::min(
+                f64::from(target_w) / f64::from(width),
+                f64::from(target_h) / f64::from(height),
+            );
+            let scale = |v: u32| ((f64::from(v) * ratio).round() as u32).max(1);
+            (scale(width), scale(height))
+        }
+        ResizeMode::Cover | ResizeMode::Pad | ResizeMode::Exact => (target_w, target_h),
+    }
+}
+
+/// Reamostra a imagem para exatamente (width, height) com o filtro escolhido.
+pub fn resample(img: &DynamicImage, width: u32, height: u32, opts: &ResizeOptions) -> DynamicImage {
+    let filter = opts.filter.filter_type();
+    in_light(img, opts, |i| i.resize_exact(width, height, filter))
+}
+
+/// Executa `op` em luz linear quando pedido, voltando depois ao tipo de cor
+/// original. Imagens em ponto flutuante (EXR/HDR) já são lineares.
+fn in_light(
+    img: &DynamicImage,
+    opts: &ResizeOptions,
+    op: impl Fn(&DynamicImage) -> DynamicImage,
+) -> DynamicImage {
+    let is_float = matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    if !opts.linear_light || is_float {
+        return op(img);
+    }
+
+    let mut linear = img.to_rgba32f();
+    for p in linear.pixels_mut() {
+        for c in &mut p.0[..3] {
+            *c = srgb_to_linear(*c);
+        }
+    }
+
+    let mut out = op(&DynamicImage::ImageRgba32F(linear)).into_rgba32f();
+    for p in out.pixels_mut() {
+        for c in &mut p.0[..3] {
+            *c = linear_to_srgb(c.clamp(0.0, 1.0));
+        }
+        p.0[3] = p.0[3].clamp(0.0, 1.0);
+    }
+
+    convert_to(DynamicImage::ImageRgba32F(out), img.color())
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converte a imagem para o tipo de cor indicado.
+fn convert_to(img: DynamicImage, color: ColorType) -> DynamicImage {
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(img.to_luma8()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(img.to_rgb8()),
+        ColorType::L16 => DynamicImage::ImageLuma16(img.to_luma16()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(img.to_luma_alpha16()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(img.to_rgb16()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(img.to_rgba16()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(img.to_rgb32f()),
+        ColorType::Rgba32F => img,
+        _ => DynamicImage::ImageRgba8(img.to_rgba8()),
+    }
+}
+
+/// Geometria de redimensionamento no estilo do ImageMagick.
+///
+/// Aceita `800x600`, `800x` ou `800` (só largura), `x600` (só altura), `50%`,
+/// os sufixos `>` (só reduz) e `<` (só amplia) e multiplicadores de densidade
+/// como `400x300@2x` ou apenas `@0.5x`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Geometry {
+    size: GeometrySize,
+    constraint: Constraint,
+    density: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GeometrySize {
+    Dimensions {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+    Percent(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Constraint {
+    Always,
+    ShrinkOnly,
+    EnlargeOnly,
+}
+
+impl Geometry {
+    /// Geometria `LARGURAx>`: largura máxima mantendo a proporção, sem ampliar.
+    pub fn max_width(width: u32) -> Self {
+        Geometry {
+            size: GeometrySize::Dimensions {
+                width: Some(width),
+                height: None,
+            },
+            constraint: Constraint::ShrinkOnly,
+            density: 1.0,
+        }
+    }
+
+    /// Calcula as dimensões finais para uma imagem de `width`x`height`.
+    ///
+    /// Devolve `None` quando a imagem deve ficar como está (por `>`/`<` ou por
+    /// já ter o tamanho pedido).
+    pub fn target(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (w, h) = match self.size {
+            GeometrySize::Percent(p) => (scale(width, p / 100.0), scale(height, p / 100.0)),
+            GeometrySize::Dimensions {
+                width: Some(w),
+                height: Some(h),
+            } => (w, h),
+            GeometrySize::Dimensions {
+                width: Some(w),
+                height: None,
+            } => (w, scale(height, f64::from(w) / f64::from(width))),
+            GeometrySize::Dimensions {
+                width: None,
+                height: Some(h),
+            } => (scale(width, f64::from(h) / f64::from(height)), h),
+            GeometrySize::Dimensions {
+                width: None,
+                height: None,
+            } => (width, height),
+        };
+        let (w, h) = (scale(w, self.density), scale(h, self.density));
+
+        let skip = match self.constraint {
+            Constraint::Always => false,
+            Constraint::ShrinkOnly => width <= w && height <= h,
+            Constraint::EnlargeOnly => width >= w && height >= h,
+        };
+
+        if skip || (w, h) == (width, height) {
+            None
+        } else {
+            Some((w, h))
+        }
+    }
+}
+
+impl FromStr for Geometry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "geometria inválida: {s} (use por exemplo 800x600, 800x, x600, 50%, 800x600> ou @2x)"
+            )
+        };
+
+        let (rest, constraint) = if let Some(rest) = s.strip_suffix('>') {
+            (rest, Constraint::ShrinkOnly)
+        } else if let Some(rest) = s.strip_suffix('<') {
+            (rest, Constraint::EnlargeOnly)
+        } else {
+            (s, Constraint::Always)
+        };
+
+        let (body, density) = match rest.split_once('@') {
+            Some((body, density)) => {
+                let density: f64 = density
+                    .strip_suffix('x')
+                    .and_then(|d| d.parse().ok())
+                    .filter(|d: &f64| d.is_finite() && *d > 0.0)
+                    .ok_or_else(invalid)?;
+                (body, density)
+            }
+            None => (rest, 1.0),
+        };
+
+        let size = if let Some(percent) = body.strip_suffix('%') {
+            let percent: f64 = percent
+                .parse()
+                .ok()
+                .filter(|p: &f64| p.is_finite() && *p > 0.0)
+                .ok_or_else(invalid)?;
+            GeometrySize::Percent(percent)
+        } else {
+            let (w, h) = body.split_once('x').unwrap_or((body, ""));
+            let dimension = |d: &str| -> Result<Option<u32>, String> {
+                if d.is_empty() {
+                    return Ok(None);
+                }
+                d.parse()
+                    .ok()
+                    .filter(|&v| v > 0)
+                    .map(Some)
+                    .ok_or_else(invalid)
+            };
+            let (width, height) = (dimension(w)?, dimension(h)?);
+            // Só "@2x" sozinho pode vir sem nenhuma dimensão
+            if width.is_none() && height.is_none() && (!body.is_empty() || !rest.contains('@')) {
+                return Err(invalid());
+            }
+            GeometrySize::Dimensions { width, height }
+        };
+
+        Ok(Geometry {
+            size,
+            constraint,
+            density,
+        })
+    }
+}
+
+/// Multiplica uma dimensão por um fator, arredondando e nunca chegando a zero.
+fn scale(value: u32, factor: f64) -> u32 {
+    ((f64::from(value) * factor).round() as u32).max(1)
+}
+
+/// Interpreta uma cor como "#rgb", "#rrggbb", "#rrggbbaa" ou um nome simples
+/// (white, black, transparent).
+pub fn parse_color(s: &str) -> Result<Rgba<u8>, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "white" => return Ok(Rgba([255, 255, 255, 255])),
+        "black" => return Ok(Rgba([0, 0, 0, 255])),
+        "transparent" => return Ok(Rgba([0, 0, 0, 0])),
+        _ => {}
+    }
+
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let invalid =
+        || format!("cor inválida: {s} (use #rrggbb, #rrggbbaa ou white/black/transparent)");
+    // from_str_radix aceitaria um sinal, como em "#+fffff"
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let digit = |i: usize, len: usize| {
+        hex.get(i..i + len)
+            .and_then(|d| u8::from_str_radix(d, 16).ok())
+            .ok_or_else(invalid)
+    };
+
+    match hex.len() {
+        3 => {
+            let expand = |i| digit(i, 1).map(|d| d * 17);
+            Ok(Rgba([expand(0)?, expand(1)?, expand(2)?, 255]))
+        }
+        6 => Ok(Rgba([digit(0, 2)?, digit(2, 2)?, digit(4, 2)?, 255])),
+        8 => Ok(Rgba([
+            digit(0, 2)?,
+            digit(2, 2)?,
+            digit(4, 2)?,
+            digit(6, 2)?,
+        ])),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(geometry: &str, width: u32, height: u32) -> Option<(u32, u32)> {
+        geometry.parse::<Geometry>().unwrap().target(width, height)
+    }
+
+    #[test]
+    fn cor_em_hexadecimal_ou_por_nome() {
+        assert_eq!(parse_color("#ff8000"), Ok(Rgba([255, 128, 0, 255])));
+        assert_eq!(parse_color("FF8000"), Ok(Rgba([255, 128, 0, 255])));
+        assert_eq!(parse_color("#f80"), Ok(Rgba([255, 136, 0, 255])));
+        assert_eq!(parse_color("#ff800080"), Ok(Rgba([255, 128, 0, 128])));
+        assert_eq!(parse_color("White"), Ok(Rgba([255, 255, 255, 255])));
+        assert_eq!(parse_color("black"), Ok(Rgba([0, 0, 0, 255])));
+        assert_eq!(parse_color("transparent"), Ok(Rgba([0, 0, 0, 0])));
+    }
+
+    #[test]
+    fn cor_invalida() {
+        for s in [
+            "", "#", "#ff", "#ff80", "#ff800", "#ff80008", "#gg0000", "#+fffff", "#ff 000", "red",
+            "#ffé00",
+        ] {
+            assert!(parse_color(s).is_err(), "{s:?} deveria ser inválida");
+        }
+    }
+
+    #[test]
+    fn geometria_so_largura_ou_so_altura_mantem_a_proporcao() {
+        assert_eq!(target("800x", 1600, 1200), Some((800, 600)));
+        assert_eq!(target("800", 1600, 1200), Some((800, 600)));
+        assert_eq!(target("x600", 1600, 1200), Some((800, 600)));
+        assert_eq!(target("800x300", 1600, 1200), Some((800, 300)));
+    }
+
+    #[test]
+    fn geometria_em_porcentagem_arredonda_e_nunca_zera() {
+        assert_eq!(target("50%", 1600, 1200), Some((800, 600)));
+        assert_eq!(target("50%", 1001, 501), Some((501, 251)));
+        assert_eq!(target("12.5%", 800, 80), Some((100, 10)));
+        assert_eq!(target("1%", 10, 10), Some((1, 1)));
+        assert_eq!(target("100%", 640, 480), None);
+    }
+
+    #[test]
+    fn geometria_com_limites_so_reduz_ou_so_amplia() {
+        assert_eq!(target("800x600>", 1600, 1200), Some((800, 600)));
+        assert_eq!(target("800x600>", 640, 480), None);
+        assert_eq!(target("800x>", 800, 2000), None);
+        assert_eq!(target("800x600<", 400, 300), Some((800, 600)));
+        assert_eq!(target("800x600<", 1600, 1200), None);
+        assert_eq!(target("800x600", 800, 600), None);
+    }
+
+    #[test]
+    fn geometria_com_densidade() {
+        assert_eq!(target("400x300@2x", 100, 100), Some((800, 600)));
+        assert_eq!(target("@0.5x", 1000, 501), Some((500, 251)));
+        assert_eq!(target("400x@2x>", 640, 480), None);
+        assert_eq!(target("@1x", 640, 480), None);
+        assert_eq!(Geometry::max_width(640), "640x>".parse().unwrap());
+    }
+
+    #[test]
+    fn geometria_invalida() {
+        for s in [
+            "",
+            "x",
+            "0x600",
+            "800x0",
+            "-800x",
+            "abc",
+            "800x600x3",
+            "800x600>>",
+            "0%",
+            "-5%",
+            "nan%",
+            "inf%",
+            "%",
+            "@",
+            "@2",
+            "@0x",
+            "@-1x",
+            "800@2x@2x",
+            "800x600 ",
+        ] {
+            assert!(s.parse::<Geometry>().is_err(), "{s:?} deveria ser inválida");
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use clap::Args;
+use serde::Serialize;
+
+use crate::batch::{BatchOptions, process_all};
+use crate::format::{EncodeOptions, OutputFormat};
+use crate::job::{Action, ImageReport, Job, Variant};
+use crate::ops::{self, Operation};
+use crate::resize::ResizeOptions;
+use crate::walk::{Inputs, WalkOptions};
+
+/// Opções do subcomando `responsive`.
+#[derive(Args, Debug)]
+pub struct ResponsiveArgs {
+    #[command(flatten)]
+    inputs: Inputs,
+
+    /// Diretório de saída
+    #[arg(long, default_value = "output")]
+    output: PathBuf,
+
+    /// Larguras da escada (nunca amplia; acima da original gera só a original,
+    /// com a largura dela no nome)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "320,640,960,1280,1920",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    widths: Vec<u32>,
+
+    /// Formatos gerados, do preferido ao fallback; o último vai no `<img>`
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "webp,jpg")]
+    to_format: Vec<OutputFormat>,
+
+    /// Valor do atributo `sizes` (ex: "(max-width: 600px) 100vw, 50vw")
+    #[arg(long, default_value = "100vw")]
+    sizes: String,
+
+    /// Prefixo das URLs no srcset (ex: /static/img/); padrão: caminho relativo
+    /// ao diretório de saída
+    #[arg(long, default_value = "")]
+    url_prefix: String,
+
+    /// Operação aplicada antes da escada de larguras (pode repetir, como em --op)
+    #[arg(long = "op", value_name = "OPERAÇÃO")]
+    ops: Vec<String>,
+
+    #[command(flatten)]
+    encode: EncodeOptions,
+
+    #[command(flatten)]
+    resize_opts: ResizeOptions,
+
+    /// Manifesto JSON (padrão: <output>/responsive.json)
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
+    /// Arquivo HTML com os trechos `<picture>` de todas as imagens
+    #[arg(long)]
+    html: Option<PathBuf>,
+}
+
+/// Entrada do manifesto: as versões de uma imagem e o HTML pronto para colar.
+#[derive(Serialize, Debug)]
+struct Entry {
+    input: String,
+    width: u32,
+    height: u32,
+    sizes: String,
+    sources: Vec<Source>,
+    img: Img,
+    html: String,
+}
+
+/// Um `<source>` do `<picture>`.
+#[derive(Serialize, Debug)]
+struct Source {
+    #[serde(rename = "type")]
+    mime_type: String,
+    srcset: String,
+}
+
+/// O `<img>` de fallback.
+#[derive(Serialize, Debug)]
+struct Img {
+    src: String,
+    srcset: String,
+    width: u32,
+    height: u32,
+}
+
+/// Gera as versões de cada imagem e grava o manifesto (e o HTML, se pedido).
+pub fn run(args: &ResponsiveArgs, batch: &BatchOptions, walk: &WalkOptions) -> Result<()> {
+    let pipeline = args
+        .ops
+        .iter()
+        .map(|spec| ops::parse_op(spec, &args.resize_opts))
+        .collect::<Result<Vec<Arc<dyn Operation>>>>()?;
+
+    let variant = Variant {
+        name: None,
+        preset: None,
+        output: args.output.clone(),
+        format: None,
+        encode: args.encode.clone(),
+        resize_opts: args.resize_opts.clone(),
+        max_bytes: None,
+        pipeline,
+        width: None,
+        previous_width: None,
+    };
+    let job = Job {
+        variants: variant.expand(&args.widths, &args.to_format),
+    };
+
+    // Saídas recusadas não existem no disco e ficam fora do srcset
+    let mut reports = process_all(args.inputs.collect(walk)?, &job, batch)?;
+    reports.retain(|r| r.action != Action::Refused);
+
+    let entries: Vec<Entry> = reports
+        .chunk_by(|a, b| a.input == b.input)
+        .filter_map(|renditions| entry(renditions, args))
+        .collect();
+
+    let manifest = args
+        .manifest
+        .clone()
+        .unwrap_or_else(|| args.output.join("responsive.json"));
+    let snippets: Vec<String> = entries
+        .iter()
+        .map(|e| format!("<!-- {} -->\n{}", comment(&e.input), e.html))
+        .collect();
+
+    // Na simulação o HTML vai para o console em vez de ser gravado
+    if batch.dry_run {
+        println!("Manifesto seria salvo em: {}", manifest.display());
+        println!("{}", snippets.join("\n\n"));
+        return Ok(());
+    }
+
+    fs::write(&manifest, serde_json::to_string_pretty(&entries)?)?;
+    println!("Manifesto salvo em: {}", manifest.display());
+
+    if let Some(html) = &args.html {
+        fs::write(html, snippets.join("\n\n") + "\n")?;
+        println!("HTML salvo em: {}", html.display());
+    }
+
+    Ok(())
+}
+
+/// Monta a entrada do manifesto a partir das versões geradas de uma imagem.
+fn entry(renditions: &[ImageReport], args: &ResponsiveArgs) -> Option<Entry> {
+    let srcset = |format: OutputFormat| {
+        let mut items: Vec<&ImageReport> =
+            renditions.iter().filter(|r| r.format == format).collect();
+        items.sort_by_key(|r| r.width);
+        items
+    };
+
+    // O fallback é o último formato que foi gerado com sucesso
+    let fallback = args
+        .to_format
+        .iter()
+        .rev()
+        .copied()
+        .find(|&f| !srcset(f).is_empty())?;
+    let fallback_items = srcset(fallback);
+    let largest = fallback_items.last()?;
+
+    let sources: Vec<Source> = args
+        .to_format
+        .iter()
+        .copied()
+        .filter(|&f| f != fallback)
+        .filter_map(|f| {
+            let items = srcset(f);
+            (!items.is_empty()).then(|| Source {
+                mime_type: f.mime_type().to_string(),
+                srcset: srcset_attr(&items, args),
+            })
+        })
+        .collect();
+
+    let img = Img {
+        src: url(largest, args),
+        srcset: srcset_attr(&fallback_items, args),
+        width: largest.width,
+        height: largest.height,
+    };
+
+    let mut html = String::from("<picture>\n");
+    for source in &sources {
+        html.push_str(&format!(
+            "  <source type=\"{}\" srcset=\"{}\" sizes=\"{}\">\n",
+            source.mime_type,
+            escape(&source.srcset),
+            escape(&args.sizes)
+        ));
+    }
+    html.push_str(&format!(
+        "  <img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"\" loading=\"lazy\" decoding=\"async\">\n",
+        escape(&img.src),
+        escape(&img.srcset),
+        escape(&args.sizes),
+        img.width,
+        img.height
+    ));
+    html.push_str("</picture>");
+
+    Some(Entry {
+        input: largest.input.clone(),
+        width: largest.width,
+        height: largest.height,
+        sizes: args.sizes.clone(),
+        sources,
+        img,
+        html,
+    })
+}
+
+/// `a-320w.webp 320w, a-640w.webp 640w, ...`
+fn srcset_attr(items: &[&ImageReport], args: &ResponsiveArgs) -> String {
+    items
+        .iter()
+        .map(|r| format!("{} {}w", url(r, args), r.width))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// URL da saída: caminho relativo ao diretório de saída, com `/`, após o prefixo.
+///
+/// Cada parte do caminho é codificada (`foto praia.webp` vira
+/// `foto%20praia.webp`), já que espaços e vírgulas separam os itens do `srcset`.
+fn url(report: &ImageReport, args: &ResponsiveArgs) -> String {
+    let output = Path::new(&report.output);
+    let relative = output.strip_prefix(&args.output).unwrap_or(output);
+    let path: Vec<_> = relative
+        .components()
+        .map(|c| percent_encode(&c.as_os_str().to_string_lossy()))
+        .collect();
+
+    let mut url = args.url_prefix.clone();
+    if !url.is_empty() && !url.ends_with('/') {
+        url.push('/');
+    }
+    url + &path.join("/")
+}
+
+/// Codifica tudo que não é letra, dígito ou `-._~` como `%XX` (bytes UTF-8).
+fn percent_encode(segment: &str) -> String {
+    let mut encoded = String::new();
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Escapa um valor para uso dentro de atributo HTML entre aspas duplas.
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Texto para dentro de `<!-- ... -->`: um `--` (como em `foto--final.png` ou
+/// `a-->b.png`) fecharia ou invalidaria o comentário.
+fn comment(text: &str) -> String {
+    let mut text = text.to_string();
+    while text.contains("--") {
+        text = text.replace("--", "- -");
+    }
+    text
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::batch::BatchOptions;
+use crate::job::{self, InputFile, Job};
+
+/// Nome da entrada e da saída padrão nos relatórios.
+const STDIO: &str = "-";
+
+/// Processa uma imagem lida da entrada padrão e grava o resultado na saída
+/// padrão, para usar o img-tool como filtro (`img-tool - --to-format webp > a.webp`).
+///
+/// Como a saída padrão leva a imagem, as mensagens vão todas para stderr.
+pub fn run(job: &Job, batch: &BatchOptions, report: Option<&Path>) -> Result<()> {
+    let [variant] = job.variants.as_slice() else {
+        bail!(
+            "com a entrada padrão só dá para gerar uma saída (um formato em --to-format e sem --widths)"
+        );
+    };
+    if !batch.dry_run && io::stdout().is_terminal() {
+        bail!("a imagem gerada iria para o terminal; redirecione a saída (ex: > saida.webp)");
+    }
+
+    let mut data = Vec::new();
+    io::stdin()
+        .read_to_end(&mut data)
+        .context("não foi possível ler a imagem da entrada padrão")?;
+
+    let file = InputFile {
+        path: PathBuf::from(STDIO),
+        relative: PathBuf::from(STDIO),
+        entry: None,
+    };
+    let Some(header) = job::read_header_from(&file, &data)? else {
+        bail!("a entrada padrão não é uma imagem em formato conhecido");
+    };
+    let limits = batch.limits();
+    limits.check(&header)?;
+
+    let mut rendition = if batch.dry_run {
+        job::plan_image(&header, variant)
+    } else {
+        let source = job::decode_from(header, &data, &limits)?;
+        job::process_image(&source, variant)?
+    };
+    rendition.report.output = STDIO.to_string();
+
+    let info = &rendition.report;
+    match &rendition.data {
+        Some(data) => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(data)?;
+            stdout.flush()?;
+            eprintln!(
+                "OK  -> saída padrão ({}, {}x{}, {} bytes)",
+                info.new_format,
+                info.width,
+                info.height,
+                data.len()
+            );
+        }
+        None => eprintln!(
+            "DRY -> saída padrão ({}, {}x{})",
+            info.new_format, info.width, info.height
+        ),
+    }
+
+    if let Some(report_path) = report {
+        let json = serde_json::to_string_pretty(&[info])?;
+        fs::write(report_path, json)?;
+        eprintln!("Relatório salvo em: {}", report_path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use anyhow::{Result, bail};
+use image::DynamicImage;
+
+use crate::format::{self, EncodeOptions, OutputFormat};
+use crate::resize::{self, ResizeOptions};
+
+/// Resultado de uma codificação que precisou caber num limite de bytes.
+pub struct Fitted {
+    pub data: Vec<u8>,
+    pub image: DynamicImage,
+    pub opts: EncodeOptions,
+}
+
+/// Interpreta tamanhos como "200KB", "1.5MB", "512KiB" ou "300000".
+///
+/// Unidades KB/MB/GB são decimais; KiB/MiB/GiB são binárias.
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("tamanho inválido: {s} (use por exemplo 200KB)"))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        other => return Err(format!("unidade desconhecida: {other}")),
+    };
+
+    let bytes = (number * multiplier as f64).round() as u64;
+    if bytes == 0 {
+        return Err("o tamanho precisa ser maior que zero".to_string());
+    }
+    Ok(bytes)
+}
+
+/// Codifica a imagem garantindo que o arquivo tenha no máximo `max_bytes`.
+///
+/// Primeiro faz uma busca binária na qualidade do encoder (JPEG, WebP com
+/// perdas e AVIF); se nem a menor qualidade couber, reduz as dimensões e tenta
+/// de novo até caber.
+pub fn encode_within(
+    img: &DynamicImage,
+    format: OutputFormat,
+    opts: &EncodeOptions,
+    resize_opts: &ResizeOptions,
+    max_bytes: u64,
+) -> Result<Fitted> {
+    let mut current = img.clone();
+
+    loop {
+        let (data, chosen) = search_quality(&current, format, opts, max_bytes)?;
+        if data.len() as u64 <= max_bytes {
+            return Ok(Fitted {
+                data,
+                image: current,
+                opts: chosen,
+            });
+        }
+
+        if current.width() <= 1 && current.height() <= 1 {
+            bail!("não foi possível gerar o arquivo com no máximo {max_bytes} bytes");
+        }
+
+        // A área é proporcional ao tamanho do arquivo, então a escala segue a raiz
+        let ratio = (max_bytes as f64 / data.len() as f64).sqrt() * 0.95;
+        let scale = ratio.clamp(0.5, 0.9);
+        let width = ((current.width() as f64 * scale).round() as u32).max(1);
+        let height = ((current.height() as f64 * scale).round() as u32).max(1);
+        current = resize::resample(img, width, height, resize_opts);
+    }
+}
+
+/// Procura a maior qualidade que cabe no limite. Devolve a menor tentativa
+/// quando nenhuma cabe, para o chamador decidir se reduz a imagem.
+fn search_quality(
+    img: &DynamicImage,
+    format: OutputFormat,
+    opts: &EncodeOptions,
+    max_bytes: u64,
+) -> Result<(Vec<u8>, EncodeOptions)> {
+    let data = format::encode(img, format, opts)?;
+    let Some(max_quality) = opts.quality(format) else {
+        // Formato sem ajuste de qualidade: só resta reduzir as dimensões
+        return Ok((data, opts.clone()));
+    };
+    if data.len() as u64 <= max_bytes {
+        return Ok((data, opts.clone()));
+    }
+
+    let mut lo = 1;
+    let mut hi = max_quality.saturating_sub(1);
+    let mut best = None;
+    let mut smallest = (data, opts.clone());
+
+    while lo <= hi {
+        let mid = lo + (hi - lo) / 2;
+        let candidate = opts.with_quality(format, mid);
+        let data = format::encode(img, format, &candidate)?;
+
+        if data.len() as u64 <= max_bytes {
+            best = Some((data, candidate));
+            lo = mid + 1;
+        } else {
+            if data.len() < smallest.0.len() {
+                smallest = (data, candidate);
+            }
+            if mid == 1 {
+                break;
+            }
+            hi = mid - 1;
+        }
+    }
+
+    Ok(best.unwrap_or(smallest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tamanho_com_unidades_decimais_e_binarias() {
+        assert_eq!(parse_byte_size("300000"), Ok(300_000));
+        assert_eq!(parse_byte_size("200KB"), Ok(200_000));
+        assert_eq!(parse_byte_size("200k"), Ok(200_000));
+        assert_eq!(parse_byte_size("1.5MB"), Ok(1_500_000));
+        assert_eq!(parse_byte_size("2gb"), Ok(2_000_000_000));
+        assert_eq!(parse_byte_size("512KiB"), Ok(512 * 1024));
+        assert_eq!(parse_byte_size("1.5MiB"), Ok(1_572_864));
+        assert_eq!(parse_byte_size(" 200 kb "), Ok(200_000));
+        assert_eq!(parse_byte_size("10B"), Ok(10));
+    }
+
+    #[test]
+    fn tamanho_arredonda_para_o_byte_mais_proximo() {
+        assert_eq!(parse_byte_size("1.0005KB"), Ok(1_001));
+        assert_eq!(parse_byte_size("0.0005KB"), Ok(1));
+    }
+
+    #[test]
+    fn tamanho_invalido() {
+        for s in [
+            "", "KB", "abc", "-5KB", "1.2.3MB", "1e3", "10XB", "0", "0KB", "0.0001KB",
+        ] {
+            assert!(parse_byte_size(s).is_err(), "{s:?} deveria ser inválido");
+        }
+    }
+}
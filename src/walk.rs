@@ -0,0 +1,447 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, ValueEnum};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+
+use crate::archive;
+use crate::job::InputFile;
+
+/// Arquivo de ignore próprio do img-tool, com a mesma sintaxe do `.gitignore`.
+const IGNORE_FILE: &str = ".imgtoolignore";
+
+/// Entradas da linha de comando.
+#[derive(Args, Debug)]
+pub struct Inputs {
+    /// Arquivos, diretórios ou padrões glob (ex: "fotos/**/*.jpg"; os padrões
+    /// são expandidos pelo img-tool, então funcionam entre aspas em qualquer shell).
+    /// Com `-`, lê uma imagem da entrada padrão e grava o resultado na saída padrão
+    #[arg(required_unless_present = "files_from", value_name = "ENTRADA")]
+    inputs: Vec<PathBuf>,
+
+    /// Lê mais entradas de um arquivo (ou da entrada padrão com `-`), uma por
+    /// linha ou separadas por NUL, como em `find -print0`
+    #[arg(long, value_name = "LISTA")]
+    files_from: Option<PathBuf>,
+}
+
+impl Inputs {
+    /// Diz se a única entrada é a imagem na entrada padrão (`-`).
+    pub fn is_stdin(&self) -> bool {
+        self.files_from.is_none() && self.inputs.len() == 1 && self.inputs[0] == Path::new("-")
+    }
+
+    /// Coleta os arquivos de todas as entradas, na ordem em que foram
+    /// passadas; um arquivo que aparece mais de uma vez é processado só uma.
+    ///
+    /// Arquivos listados em `--files-from` com caminho relativo mantêm esse
+    /// caminho na saída (`a/b.png` vira `output/a/b.png`).
+    pub fn collect(&self, walk: &WalkOptions) -> Result<Vec<InputFile>> {
+        if self.inputs.iter().any(|input| input == Path::new("-")) {
+            bail!("`-` (imagem na entrada padrão) precisa ser a única entrada");
+        }
+
+        let mut files = Vec::new();
+        for input in &self.inputs {
+            files.extend(collect_paths(input, walk)?);
+        }
+
+        if let Some(list) = &self.files_from {
+            for input in read_list(list)? {
+                if input.is_file() && !archive::is_archive(&input) {
+                    let relative = listed_relative(&input);
+                    files.push(InputFile {
+                        path: input,
+                        relative,
+                        entry: None,
+                    });
+                } else {
+                    files.extend(collect_paths(&input, walk)?);
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        files.retain(|f| seen.insert(identity(f)));
+        Ok(files)
+    }
+}
+
+/// Identifica o arquivo independente de como o caminho foi escrito (`a.png`
+/// e `./a.png` são o mesmo): o caminho canônico ou, para entradas de
+/// pacotes, o do pacote mais o caminho dentro dele.
+fn identity(file: &InputFile) -> PathBuf {
+    let disk = file.disk_path();
+    let canonical = disk.canonicalize().unwrap_or_else(|_| disk.to_path_buf());
+    match &file.entry {
+        Some(entry) => archive::virtual_path(&canonical, entry.name()),
+        None => canonical,
+    }
+}
+
+/// Lê a lista de `--files-from`. Se houver algum NUL, ele é o separador;
+/// senão, cada linha é um caminho.
+fn read_list(list: &Path) -> Result<Vec<PathBuf>> {
+    let mut bytes = Vec::new();
+    if list == Path::new("-") {
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .context("não foi possível ler a lista de arquivos da entrada padrão")?;
+    } else {
+        bytes =
+            fs::read(list).with_context(|| format!("não foi possível ler {}", list.display()))?;
+    }
+    let text = String::from_utf8(bytes)
+        .with_context(|| format!("a lista de arquivos {} não é UTF-8", list.display()))?;
+
+    let separator = if text.contains('\0') { '\0' } else { '\n' };
+    Ok(text
+        .split(separator)
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Caminho na saída de um arquivo listado: o próprio caminho, se for relativo
+/// e não sair do diretório atual; senão, só o nome do arquivo.
+fn listed_relative(path: &Path) -> PathBuf {
+    let inside = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if inside {
+        path.components().collect()
+    } else {
+        PathBuf::from(path.file_name().unwrap_or_default())
+    }
+}
+
+/// Opções de como os diretórios de entrada são percorridos, aceitas por todos
+/// os subcomandos.
+#[derive(Args, Debug, Clone)]
+pub struct WalkOptions {
+    /// Processa só os arquivos que casam com o padrão, comparado com o caminho
+    /// relativo à entrada (pode repetir). `*` não passa de uma pasta para
+    /// outra: "*.png" vale só para a raiz da entrada, "**/*.png" para todas
+    #[arg(long, global = true, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Pula arquivos e pastas que casam com o padrão, com as mesmas regras de
+    /// --include (ex: "**/rascunhos"; pode repetir). Pastas excluídas nem são
+    /// percorridas
+    #[arg(long, global = true, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Profundidade máxima dentro dos diretórios de entrada (1: só os arquivos
+    /// que estão direto neles)
+    #[arg(long, global = true)]
+    max_depth: Option<usize>,
+
+    /// Inclui arquivos e pastas ocultos (começados por `.`)
+    #[arg(long, global = true)]
+    hidden: bool,
+
+    /// Não respeita `.gitignore` nem `.imgtoolignore`
+    #[arg(long, global = true)]
+    no_ignore: bool,
+
+    /// O que fazer com links simbólicos dentro dos diretórios de entrada
+    #[arg(long, global = true, value_enum, default_value_t = Symlinks::Files)]
+    symlinks: Symlinks,
+}
+
+/// Política para links simbólicos encontrados ao percorrer a entrada.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Ignora todos os links
+    Skip,
+    /// Segue links para arquivos, mas não entra em links para pastas
+    Files,
+    /// Segue todos os links (ciclos são detectados e reportados)
+    Follow,
+}
+
+/// Coleta todos os arquivos a partir de um arquivo único ou diretório,
+/// guardando o caminho de cada um relativo à entrada.
+///
+/// Um arquivo passado diretamente é sempre incluído; nos diretórios valem os
+/// filtros de `walk`, aplicados antes de qualquer arquivo ser aberto. Pacotes
+/// (.zip, .tar, .tar.gz) são lidos como diretórios: dentro de um diretório,
+/// as entradas de `fotos.zip` vão para a pasta `fotos` da saída.
+pub fn collect_paths(input: &Path, walk: &WalkOptions) -> Result<Vec<InputFile>> {
+    let mut files = Vec::new();
+    let include = glob_set(&walk.include)?;
+    let exclude = glob_set(&walk.exclude)?;
+
+    if input.is_file() && archive::is_archive(input) {
+        files = archive_files(input, Path::new(""), walk, &include, &exclude);
+    } else if input.is_file() {
+        files.push(InputFile {
+            path: input.to_path_buf(),
+            relative: PathBuf::from(input.file_name().unwrap_or_default()),
+            entry: None,
+        });
+    } else if input.is_dir() {
+        files = collect_dir(input, None, walk, &include, &exclude)?;
+    } else if is_glob(input) {
+        files = expand_glob(input, walk)?;
+        if files.is_empty() {
+            eprintln!("Nenhum arquivo corresponde a {}", input.display());
+        }
+    } else {
+        eprintln!("Entrada não é arquivo nem diretório: {}", input.display());
+    }
+
+    Ok(files)
+}
+
+/// Como [`collect_paths`] num diretório, mas percorrendo só os caminhos em
+/// `touched` (arquivos ou pastas dentro de `input`) e as pastas até eles; os
+/// filtros valem como se o diretório inteiro fosse percorrido.
+pub fn collect_touched(
+    input: &Path,
+    touched: &[PathBuf],
+    walk: &WalkOptions,
+) -> Result<Vec<InputFile>> {
+    let include = glob_set(&walk.include)?;
+    let exclude = glob_set(&walk.exclude)?;
+    collect_dir(input, Some(touched), walk, &include, &exclude)
+}
+
+fn collect_dir(
+    input: &Path,
+    only: Option<&[PathBuf]>,
+    walk: &WalkOptions,
+    include: &GlobSet,
+    exclude: &GlobSet,
+) -> Result<Vec<InputFile>> {
+    let mut files = Vec::new();
+    for path in walk_dir(input, only, walk, exclude)? {
+        let relative = path.strip_prefix(input).unwrap_or(&path);
+        if archive::is_archive(&path) {
+            // --include vale para as entradas, não para o pacote
+            if !exclude.is_match(relative) {
+                let prefix = relative.with_file_name(archive::stem(&path));
+                files.extend(archive_files(&path, &prefix, walk, include, exclude));
+            }
+            continue;
+        }
+
+        let included = walk.include.is_empty() || include.is_match(relative);
+        if included && !exclude.is_match(relative) {
+            files.push(InputFile {
+                relative: relative.to_path_buf(),
+                path,
+                entry: None,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Arquivos de um diretório, ordenados, com os filtros de `walk` menos
+/// --include; pastas que casam com `exclude` nem são percorridas. Com
+/// `only`, desce só pelas pastas que levam a esses caminhos.
+fn walk_dir(
+    input: &Path,
+    only: Option<&[PathBuf]>,
+    walk: &WalkOptions,
+    exclude: &GlobSet,
+) -> Result<Vec<PathBuf>> {
+    let mut builder = WalkBuilder::new(input);
+    builder
+        .standard_filters(false)
+        .hidden(!walk.hidden)
+        .git_ignore(!walk.no_ignore)
+        .parents(!walk.no_ignore)
+        .require_git(false)
+        .max_depth(walk.max_depth)
+        .follow_links(walk.symlinks == Symlinks::Follow)
+        // Ordena para que a ordem da saída não dependa do sistema de arquivos
+        .sort_by_file_name(|a, b| a.cmp(b));
+    if !walk.no_ignore {
+        builder.add_custom_ignore_filename(IGNORE_FILE);
+    }
+
+    // Poda as pastas excluídas em vez de percorrê-las
+    let root = input.to_path_buf();
+    let pruned = exclude.clone();
+    let only = only.map(<[PathBuf]>::to_vec);
+    builder.filter_entry(move |entry| {
+        let path = entry.path();
+        let wanted = only.as_ref().is_none_or(|only| {
+            only.iter()
+                .any(|touched| touched.starts_with(path) || path.starts_with(touched))
+        });
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        let relative = path.strip_prefix(&root).unwrap_or(path);
+        wanted && !(is_dir && entry.depth() > 0 && pruned.is_match(relative))
+    });
+
+    let mut paths = Vec::new();
+    for entry in builder.build() {
+        let entry = entry?;
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        let is_file = if file_type.is_symlink() {
+            walk.symlinks == Symlinks::Files && path.is_file()
+        } else {
+            file_type.is_file()
+        };
+        if is_file {
+            paths.push(entry.into_path());
+        }
+    }
+    Ok(paths)
+}
+
+/// Arquivos de um pacote, com os mesmos filtros dos diretórios (exceto
+/// `.gitignore`); `prefix` é o caminho do pacote como pasta na saída.
+///
+/// Um pacote que não pode ser lido é reportado e pulado, sem parar o lote.
+fn archive_files(
+    path: &Path,
+    prefix: &Path,
+    walk: &WalkOptions,
+    include: &GlobSet,
+    exclude: &GlobSet,
+) -> Vec<InputFile> {
+    let entries = match archive::list(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("ERR -> {}: {e:#}", path.display());
+            return Vec::new();
+        }
+    };
+
+    let mut files = Vec::new();
+    for (name, entry) in entries {
+        let hidden = name
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        let depth = prefix.components().count() + name.components().count();
+        if (hidden && !walk.hidden) || walk.max_depth.is_some_and(|max| depth > max) {
+            continue;
+        }
+
+        let relative = prefix.join(&name);
+        let included = walk.include.is_empty() || include.is_match(&relative);
+        if included && !exclude.is_match(&relative) {
+            files.push(InputFile {
+                path: archive::virtual_path(path, &name),
+                relative,
+                entry: Some(entry),
+            });
+        }
+    }
+    files
+}
+
+/// Diz se a entrada (que não existe como caminho) é um padrão glob.
+fn is_glob(input: &Path) -> bool {
+    input
+        .to_str()
+        .is_some_and(|s| s.contains(['*', '?', '[', '{']))
+}
+
+/// Expande um padrão como `fotos/**/*.jpg`, percorrendo só a parte fixa do
+/// começo (`fotos`) com os filtros de `walk`. Como no shell, `*` não passa
+/// de uma pasta para outra e `**` passa. Os caminhos relativos são contados a
+/// partir da parte fixa.
+///
+/// Pacotes entram inteiros quando o padrão casa com o próprio pacote
+/// (`*.zip`); senão, só as entradas que casam com ele (`**/*.png`).
+fn expand_glob(pattern: &Path, walk: &WalkOptions) -> Result<Vec<InputFile>> {
+    let mut base = PathBuf::new();
+    let mut rest: Vec<String> = Vec::new();
+    for component in pattern.components() {
+        let part = component.as_os_str().to_string_lossy();
+        if rest.is_empty() && !is_glob(Path::new(&*part)) {
+            base.push(component);
+        } else {
+            rest.push(part.into_owned());
+        }
+    }
+
+    let matcher = glob(&rest.join("/"))
+        .with_context(|| format!("padrão inválido: {}", pattern.display()))?
+        .compile_matcher();
+    let include = glob_set(&walk.include)?;
+    let exclude = glob_set(&walk.exclude)?;
+
+    // Sem `**`, não adianta descer mais que a quantidade de partes do padrão
+    let mut capped = walk.clone();
+    if !rest.iter().any(|part| part.contains("**")) {
+        capped.max_depth = Some(walk.max_depth.map_or(rest.len(), |d| d.min(rest.len())));
+    }
+
+    let root = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base.as_path()
+    };
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for path in walk_dir(root, None, &capped, &exclude)? {
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        // `*.png` gera `a.png`, não `./a.png`
+        let path = if base.as_os_str().is_empty() {
+            relative.clone()
+        } else {
+            path
+        };
+
+        if archive::is_archive(&path) {
+            if exclude.is_match(&relative) {
+                continue;
+            }
+            let prefix = relative.with_file_name(archive::stem(&path));
+            if matcher.is_match(&relative) {
+                files.extend(archive_files(&path, &prefix, walk, &include, &exclude));
+            } else {
+                let entries = archive_files(&path, &prefix, &capped, &include, &exclude);
+                files.extend(
+                    entries
+                        .into_iter()
+                        .filter(|f| matcher.is_match(&f.relative)),
+                );
+            }
+            continue;
+        }
+
+        let included = walk.include.is_empty() || include.is_match(&relative);
+        if matcher.is_match(&relative) && included && !exclude.is_match(&relative) {
+            files.push(InputFile {
+                path,
+                relative,
+                entry: None,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Junta os padrões num único conjunto.
+pub fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(glob(pattern).with_context(|| format!("padrão inválido: {pattern}"))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Padrão glob com a mesma regra em todo lugar (entradas, --include,
+/// --exclude e arquivos de configuração): `*` e `?` não passam de uma pasta
+/// para outra, `**` passa.
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use clap::Args;
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::archive;
+use crate::batch::{BatchOptions, process_all_with};
+use crate::job::{Action, InputFile, Job};
+use crate::walk::{WalkOptions, collect_paths, collect_touched};
+use crate::{ProcessArgs, write_report};
+
+/// Opções do subcomando `watch`.
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    /// Diretório observado
+    input: PathBuf,
+
+    #[command(flatten)]
+    process: ProcessArgs,
+
+    /// Tempo sem novas mudanças antes de processar, em milissegundos; junta
+    /// rajadas (ex: várias imagens copiadas de uma vez) numa só rodada
+    #[arg(long, default_value_t = 500, value_name = "MS")]
+    debounce: u64,
+
+    /// Verifica o diretório periodicamente em vez de usar notificações do
+    /// sistema (útil em pastas de rede, onde elas nem sempre chegam)
+    #[arg(long)]
+    poll: bool,
+
+    /// Intervalo do polling, em milissegundos
+    #[arg(long, default_value_t = 1000, value_name = "MS")]
+    poll_interval: u64,
+}
+
+/// Estado do watch: as saídas geradas por cada entrada nesta sessão.
+struct Watch<'a> {
+    input: &'a Path,
+    /// Entrada canônica, como aparece nos eventos
+    root: PathBuf,
+    /// Caminhos dentro da entrada que o próprio img-tool grava
+    ignored: Vec<PathBuf>,
+    job: Job,
+    batch: &'a BatchOptions,
+    walk: &'a WalkOptions,
+    report: Option<&'a Path>,
+    outputs: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+/// Processa o diretório e continua observando: arquivos criados ou
+/// modificados são processados de novo e, quando uma entrada é removida, as
+/// saídas geradas a partir dela são apagadas.
+pub fn run(args: &WatchArgs, batch: &BatchOptions, walk: &WalkOptions) -> Result<()> {
+    let process = &args.process;
+    let input = args.input.as_path();
+    if !input.is_dir() {
+        bail!(
+            "o watch precisa de um diretório de entrada: {}",
+            input.display()
+        );
+    }
+
+    let job = Job::from_args(process)?;
+    let mut ignored = Vec::new();
+    for variant in &job.variants {
+        if !batch.dry_run {
+            fs::create_dir_all(&variant.output)?;
+        }
+        ignored.push(absolute(&variant.output)?);
+    }
+    if let Some(report) = &process.report {
+        ignored.push(absolute(report)?);
+    }
+
+    let mut watch = Watch {
+        input,
+        root: input.canonicalize()?,
+        ignored,
+        job,
+        batch,
+        walk,
+        report: process.report.as_deref(),
+        outputs: BTreeMap::new(),
+    };
+    let files = collect_paths(input, walk)?
+        .into_iter()
+        .filter(|f| !watch.is_ignored(&f.relative))
+        .collect();
+    watch.process(files)?;
+
+    let (sender, receiver) = mpsc::channel();
+    let _watcher = watcher(&watch.root, args, sender)?;
+    println!("Observando {} (Ctrl+C para sair)", input.display());
+
+    let debounce = Duration::from_millis(args.debounce);
+    while let Ok(event) = receiver.recv() {
+        let mut changed = BTreeSet::new();
+        collect_event(event, &mut changed);
+        while let Ok(event) = receiver.recv_timeout(debounce) {
+            collect_event(event, &mut changed);
+        }
+        // Um erro numa rodada (ex: disco cheio) não encerra o watch
+        if let Err(e) = watch.update(changed) {
+            eprintln!("ERR -> {}: {e}", input.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Cria o watcher com notificações do sistema e, se não der, com polling.
+fn watcher(
+    root: &Path,
+    args: &WatchArgs,
+    sender: Sender<notify::Result<Event>>,
+) -> Result<Box<dyn Watcher>> {
+    let poll = |sender| -> Result<Box<dyn Watcher>> {
+        let config =
+            Config::default().with_poll_interval(Duration::from_millis(args.poll_interval));
+        let mut watcher = PollWatcher::new(sender, config)?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+        Ok(Box::new(watcher))
+    };
+    if args.poll {
+        return poll(sender);
+    }
+
+    let native = RecommendedWatcher::new(sender.clone(), Config::default()).and_then(|mut w| {
+        w.watch(root, RecursiveMode::Recursive)?;
+        Ok(w)
+    });
+    match native {
+        Ok(watcher) => Ok(Box::new(watcher)),
+        Err(e) => {
+            eprintln!(
+                "Notificações do sistema indisponíveis ({e}); verificando a cada {} ms",
+                args.poll_interval
+            );
+            poll(sender)
+        }
+    }
+}
+
+/// Guarda os caminhos tocados por um evento; leituras (inclusive as do
+/// próprio img-tool) não contam como mudança.
+fn collect_event(event: notify::Result<Event>, changed: &mut BTreeSet<PathBuf>) {
+    match event {
+        Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+        Ok(event) => changed.extend(event.paths),
+        Err(e) => eprintln!("Erro ao observar: {e}"),
+    }
+}
+
+impl Watch<'_> {
+    /// Processa de novo o que foi criado ou modificado e remove as saídas das
+    /// entradas que sumiram. O que aconteceu com cada caminho é decidido pelo
+    /// estado atual dele, já que uma rajada pode criar e apagar o mesmo arquivo.
+    fn update(&mut self, changed: BTreeSet<PathBuf>) -> Result<()> {
+        let mut touched = Vec::new();
+        let mut removed = Vec::new();
+
+        for path in changed {
+            let Ok(relative) = path.strip_prefix(&self.root) else {
+                continue;
+            };
+            if relative.as_os_str().is_empty() || self.is_ignored(relative) {
+                continue;
+            }
+
+            let source = self.input.join(relative);
+            if source.exists() {
+                touched.push(source);
+            } else {
+                removed.push(source);
+            }
+        }
+
+        // Percorre só o que mudou, com os mesmos filtros do início, para que
+        // --include, arquivos ocultos e .gitignore valham também aqui; pastas
+        // criadas ou movidas para dentro da entrada entram por inteiro
+        let mut files = Vec::new();
+        if !touched.is_empty() {
+            files = collect_touched(self.input, &touched, self.walk)?;
+            files.retain(|file| !self.is_ignored(&file.relative));
+        }
+
+        // Entradas que sumiram, inclusive as que saíram de um pacote modificado
+        let fresh: HashSet<&Path> = files.iter().map(|f| f.path.as_path()).collect();
+        let stale: Vec<PathBuf> = self
+            .outputs
+            .keys()
+            .filter(|input| {
+                removed.iter().any(|r| belongs_to(input, r))
+                    || (!fresh.contains(input.as_path())
+                        && touched.iter().any(|t| belongs_to(input, t)))
+            })
+            .cloned()
+            .collect();
+        for input in stale {
+            for output in self.outputs.remove(&input).unwrap_or_default() {
+                self.remove(&output);
+            }
+        }
+
+        if !files.is_empty() {
+            self.process(files)?;
+        }
+        Ok(())
+    }
+
+    /// Processa as entradas e atualiza as saídas de cada uma, apagando as que
+    /// não foram geradas de novo (ex: a imagem ficou menor que uma largura).
+    fn process(&mut self, files: Vec<InputFile>) -> Result<()> {
+        // As saídas das outras entradas continuam ocupadas
+        let inputs: HashSet<&Path> = files.iter().map(|f| f.path.as_path()).collect();
+        let claimed = self
+            .outputs
+            .iter()
+            .filter(|(input, _)| !inputs.contains(input.as_path()))
+            .flat_map(|(_, outputs)| outputs.iter().cloned())
+            .collect();
+
+        let reports = process_all_with(files, &self.job, self.batch, claimed)?;
+
+        for renditions in reports.chunk_by(|a, b| a.input == b.input) {
+            let input = PathBuf::from(&renditions[0].input);
+            let old = self.outputs.remove(&input).unwrap_or_default();
+
+            // Uma saída mantida só é desta entrada se já era antes
+            let new: Vec<PathBuf> = renditions
+                .iter()
+                .map(|r| (r.action, PathBuf::from(&r.output)))
+                .filter(|(action, output)| match action {
+                    Action::Refused => false,
+                    Action::Skipped => old.contains(output),
+                    _ => true,
+                })
+                .map(|(_, output)| output)
+                .collect();
+
+            for output in old.iter().filter(|o| !new.contains(o)) {
+                self.remove(output);
+            }
+            if !new.is_empty() {
+                self.outputs.insert(input, new);
+            }
+        }
+
+        write_report(&reports, self.report)
+    }
+
+    /// Apaga uma saída cuja entrada não existe mais.
+    fn remove(&self, output: &Path) {
+        if self.batch.dry_run {
+            println!("DRY -> {} (removido)", output.display());
+            return;
+        }
+        match fs::remove_file(output) {
+            Ok(()) => {
+                println!("DEL -> {}", output.display());
+                self.remove_empty_dirs(output);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => eprintln!("ERR -> {}: {e}", output.display()),
+        }
+    }
+
+    /// Apaga as subpastas da saída que ficaram vazias com a remoção.
+    fn remove_empty_dirs(&self, output: &Path) {
+        let Some(root) = self
+            .job
+            .variants
+            .iter()
+            .map(|v| v.output.as_path())
+            .find(|root| output.starts_with(root))
+        else {
+            return;
+        };
+        for dir in output.ancestors().skip(1) {
+            if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Diz se o caminho (relativo à entrada) é uma saída ou o relatório,
+    /// para não processar o que o próprio watch grava.
+    fn is_ignored(&self, relative: &Path) -> bool {
+        let path = self.root.join(relative);
+        self.ignored.iter().any(|ignored| path.starts_with(ignored))
+    }
+}
+
+/// Diz se a entrada está dentro de `source` (pasta ou pacote) ou é ela mesma.
+fn belongs_to(input: &Path, source: &Path) -> bool {
+    input.starts_with(source) || archive::contains(source, input)
+}
+
+/// Caminho absoluto e canônico, mesmo que ainda não exista (sem `--dry-run`
+/// as saídas já foram criadas, então só o relatório pode faltar).
+fn absolute(path: &Path) -> Result<PathBuf> {
+    match path.canonicalize() {
+        Ok(path) => Ok(path),
+        Err(_) => {
+            let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+            let parent = match parent {
+                Some(parent) => absolute(parent)?,
+                None => std::env::current_dir()?.canonicalize()?,
+            };
+            Ok(parent.join(path.file_name().unwrap_or_default()))
+        }
+    }
+}